@@ -0,0 +1,60 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use image::{Rgba, Rgba32FImage, RgbaImage};
+
+use face_stack::blending::{BlendingMode, Luma};
+use face_stack::compositing::blend_image;
+use face_stack::geom::XYWHi;
+use face_stack::masking::{Mask, MaskShape};
+
+// Size of the output image, and of the layer blended on it
+const SIZE: u32 = 1024;
+
+/// A layer with some variation, so blending isn't always with the same colors
+fn layer_image() -> RgbaImage {
+	RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+		Rgba([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8, 255])
+	})
+}
+
+fn blend_image_benchmark(c: &mut Criterion) {
+	let top = layer_image();
+	let mut group = c.benchmark_group("blend_image");
+	for mode in [BlendingMode::Normal, BlendingMode::Multiply, BlendingMode::SoftLight, BlendingMode::Hue] {
+		group.bench_with_input(BenchmarkId::from_parameter(&mode), &mode, |b, mode| {
+			let mut bottom = Rgba32FImage::from_pixel(SIZE, SIZE, Rgba([0.5, 0.5, 0.5, 1.0]));
+			b.iter(|| {
+				blend_image(&mut bottom, &top, (0, 0), [0.5, 0.5, 0.5], mode, &Luma::Rec709, None, false)
+			});
+		});
+	}
+	group.finish();
+}
+
+fn blend_image_masked_benchmark(c: &mut Criterion) {
+	let top = layer_image();
+	let rect: XYWHi = (0, 0, SIZE, SIZE);
+	let mask = Mask {
+		rect,
+		shape: MaskShape::Ellipse,
+		feather: 32.0,
+		vignette: None,
+	};
+	c.bench_function("blend_image masked linear light", |b| {
+		let mut bottom = Rgba32FImage::from_pixel(SIZE, SIZE, Rgba([0.5, 0.5, 0.5, 1.0]));
+		b.iter(|| {
+			blend_image(
+				&mut bottom,
+				&top,
+				(0, 0),
+				[0.5, 0.5, 0.5],
+				&BlendingMode::Normal,
+				&Luma::Rec709,
+				Some(&mask),
+				true,
+			)
+		});
+	});
+}
+
+criterion_group!(benches, blend_image_benchmark, blend_image_masked_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use image::{Rgba, Rgba32FImage, RgbaImage};
+
+use face_stack::blending::{BlendingMode, Luma};
+use face_stack::compositing::blend_image;
+
+// Size of the output image, and of the layer blended on it
+const SIZE: u32 = 1024;
+
+/// Blends a whole image in `multiply` mode. This uses SIMD when built with `--features simd`, and scalar code
+/// otherwise; the benchmark has the same name in both, so saving a baseline in one and comparing against it in
+/// the other compares them:
+///
+/// ```sh
+/// cargo bench --bench blending_simd -- --save-baseline scalar
+/// cargo bench --bench blending_simd --features simd -- --baseline scalar
+/// ```
+fn simd_vs_scalar_benchmark(c: &mut Criterion) {
+	let top = RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+		Rgba([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8, 255])
+	});
+	let mut bottom = Rgba32FImage::from_pixel(SIZE, SIZE, Rgba([0.5, 0.5, 0.5, 1.0]));
+	c.bench_function("blend_image_multiply", |b| {
+		b.iter(|| {
+			blend_image(
+				&mut bottom,
+				&top,
+				(0, 0),
+				[0.5, 0.5, 0.5],
+				&BlendingMode::Multiply,
+				&Luma::Rec709,
+				None,
+				false,
+			)
+		});
+	});
+}
+
+criterion_group!(benches, simd_vs_scalar_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,88 @@
+use image::{Rgba32FImage, RgbaImage};
+use strum_macros::{Display, EnumString};
+
+use crate::blending::{Luma, luma, pixel_u8_to_f32};
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum WhiteBalance {
+	#[strum(serialize = "none")]
+	None,
+	#[strum(serialize = "gray-world")]
+	GrayWorld,
+}
+
+/// Stretches the levels of each color channel (0-1) of an image so its darkest and brightest values become 0
+/// and 1, increasing the contrast. `clip` is the percentage of values ignored at each end, so a few extreme
+/// pixels don't stop the rest from being stretched. Transparent pixels are ignored.
+pub fn normalize_levels(image: &mut Rgba32FImage, clip: f32) {
+	let mut values: Vec<f32> = Vec::with_capacity(image.width() as usize * image.height() as usize);
+	for channel in 0..3 {
+		values.clear();
+		values.extend(image.pixels().filter(|pixel| pixel[3] > 0.0).map(|pixel| pixel[channel]));
+		if values.is_empty() {
+			return;
+		}
+		values.sort_unstable_by(|a, b| a.total_cmp(b));
+		let clipped = ((values.len() - 1) as f32 * clip.clamp(0.0, 100.0) / 100.0).round() as usize;
+		let low = values[clipped.min(values.len() - 1)];
+		let high = values[(values.len() - 1).saturating_sub(clipped)];
+		if high <= low {
+			// Nothing to stretch, or everything would be clipped
+			continue;
+		}
+		for pixel in image.pixels_mut() {
+			pixel[channel] = ((pixel[channel] - low) / (high - low)).clamp(0.0, 1.0);
+		}
+	}
+}
+
+/// Removes the color cast of an image by assuming its average color should be a neutral gray (the "gray world"
+/// assumption), and scaling each color channel (0-1) so its average matches the others. Transparent pixels
+/// are ignored.
+pub fn balance_gray_world(image: &mut Rgba32FImage) {
+	let mut sums = [0.0f64; 3];
+	let mut count = 0usize;
+	for pixel in image.pixels().filter(|pixel| pixel[3] > 0.0) {
+		for (sum, value) in sums.iter_mut().zip(pixel.0) {
+			*sum += value as f64;
+		}
+		count += 1;
+	}
+	if count == 0 || sums.contains(&0.0) {
+		// Nothing to balance, or a channel that can't be scaled
+		return;
+	}
+	let gray = sums.iter().sum::<f64>() / 3.0;
+	let scales = sums.map(|sum| (gray / sum) as f32);
+	for pixel in image.pixels_mut() {
+		for (value, scale) in pixel.0.iter_mut().zip(scales) {
+			*value = (*value * scale).clamp(0.0, 1.0);
+		}
+	}
+}
+
+/// Moves the colors of an image towards their luma (with the given weights), by an amount from 0 (unchanged) to 1
+/// (grayscale).
+pub fn desaturate(image: &mut RgbaImage, amount: f32, weights: &Luma) {
+	for pixel in image.pixels_mut() {
+		let color = pixel_u8_to_f32(&[pixel.0[0], pixel.0[1], pixel.0[2]]);
+		let gray = luma(color, weights);
+		for (value, channel) in pixel.0.iter_mut().zip(color) {
+			*value = ((channel + (gray - channel) * amount) * 255.0).round().clamp(0.0, 255.0) as u8;
+		}
+	}
+}
+
+/// Tints an image with a color, by replacing the colors with the color scaled by their luma (so shadows stay dark
+/// and highlights take the color), mixed with the original colors by a strength from 0 (unchanged) to 1 (fully
+/// tinted).
+pub fn tint(image: &mut RgbaImage, color: [f32; 3], strength: f32, weights: &Luma) {
+	for pixel in image.pixels_mut() {
+		let original = pixel_u8_to_f32(&[pixel.0[0], pixel.0[1], pixel.0[2]]);
+		let gray = luma(original, weights);
+		for ((value, channel), tint_channel) in pixel.0.iter_mut().zip(original).zip(color) {
+			let tinted = gray * tint_channel;
+			*value = ((channel + (tinted - channel) * strength) * 255.0).round().clamp(0.0, 255.0) as u8;
+		}
+	}
+}
@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::io::Reader;
+use image::{AnimationDecoder, Delay, DynamicImage, Frame, ImageFormat, ImageResult, RgbaImage};
+use strum_macros::{Display, EnumString};
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum FrameStrategy {
+	#[strum(serialize = "first")]
+	First,
+	#[strum(serialize = "middle")]
+	Middle,
+	#[strum(serialize = "all")]
+	All,
+}
+
+// From 1 (best quality, slowest) to 30; quantizing frames to a GIF palette is slow, so it's kept at the default
+const GIF_SPEED: i32 = 10;
+
+/// An animated GIF, with frames written to the file as they're added
+pub struct Animation {
+	encoder: GifEncoder<BufWriter<File>>,
+	delay: Delay,
+}
+
+impl Animation {
+	pub fn new(path: &Path, fps: u32) -> Self {
+		let file = File::create(path).expect("Failed to create output image");
+		let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), GIF_SPEED);
+		encoder.set_repeat(Repeat::Infinite).expect("Failed to set up the output animation");
+		Animation {
+			encoder,
+			delay: Delay::from_numer_denom_ms(1000, fps),
+		}
+	}
+
+	/// Adds a frame to the end of the animation.
+	pub fn add_frame(&mut self, image: RgbaImage) {
+		self.encoder
+			.encode_frame(Frame::from_parts(image, 0, 0, self.delay))
+			.expect("Failed to save output animation frame");
+	}
+}
+
+/**
+ * Read the frames to use from an image file. Animated GIFs can have many frames, so the strategy decides which
+ * ones are used; all other files only have one
+ */
+pub fn read_frames(path: &Path, strategy: &FrameStrategy) -> ImageResult<Vec<DynamicImage>> {
+	// The first frame is what any file decodes to, so there's no need to check the format for it
+	if *strategy == FrameStrategy::First {
+		return Ok(vec![image::open(path)?]);
+	}
+	let format = Reader::open(path)?.with_guessed_format()?.format();
+	if format != Some(ImageFormat::Gif) {
+		return Ok(vec![image::open(path)?]);
+	}
+
+	let mut frames = GifDecoder::new(BufReader::new(File::open(path)?))?.into_frames().collect_frames()?;
+	if frames.len() <= 1 {
+		return Ok(vec![image::open(path)?]);
+	}
+	if *strategy == FrameStrategy::Middle {
+		frames = vec![frames.swap_remove(frames.len() / 2)];
+	}
+	Ok(frames.into_iter().map(|frame| DynamicImage::ImageRgba8(frame.into_buffer())).collect())
+}
@@ -1,8 +1,10 @@
 // From https://github.com/zeh/random-art-generator/blob/main/src/generator/utils/color.rs
 
-use strum_macros::{Display, EnumString};
+use serde::Serialize;
+use strum_macros::{Display, EnumIter, EnumString};
 
-#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+#[derive(Clone, Debug, Display, EnumIter, EnumString, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum BlendingMode {
 	#[strum(serialize = "normal")]
 	Normal,
@@ -28,9 +30,120 @@ pub enum BlendingMode {
 	Difference,
 	#[strum(serialize = "exclusion")]
 	Exclusion,
+	#[strum(serialize = "linear-dodge", serialize = "add")]
+	LinearDodge,
+	#[strum(serialize = "linear-burn")]
+	LinearBurn,
+	#[strum(serialize = "vivid-light")]
+	VividLight,
+	#[strum(serialize = "linear-light")]
+	LinearLight,
+	#[strum(serialize = "pin-light")]
+	PinLight,
+	#[strum(serialize = "subtract")]
+	Subtract,
+	#[strum(serialize = "divide")]
+	Divide,
+	#[strum(serialize = "reflect")]
+	Reflect,
+	#[strum(serialize = "glow")]
+	Glow,
+	#[strum(serialize = "negation")]
+	Negation,
+	#[strum(serialize = "average")]
+	Average,
+	#[strum(serialize = "hue")]
+	Hue,
+	#[strum(serialize = "saturation")]
+	Saturation,
+	#[strum(serialize = "color")]
+	Color,
+	#[strum(serialize = "luminosity")]
+	Luminosity,
+	#[strum(serialize = "darker-color")]
+	DarkerColor,
+	#[strum(serialize = "lighter-color")]
+	LighterColor,
+}
+
+/// Weights of the red, green, and blue channels in the brightness of a color, used by everything based on it
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum Luma {
+	#[strum(serialize = "rec709")]
+	Rec709,
+	#[strum(serialize = "rec601")]
+	Rec601,
+	#[strum(serialize = "average")]
+	Average,
+}
+
+impl Luma {
+	pub fn weights(&self) -> [f32; 3] {
+		match self {
+			Self::Rec709 => [0.2126, 0.7152, 0.0722],
+			Self::Rec601 => [0.299, 0.587, 0.114],
+			Self::Average => [1.0 / 3.0; 3],
+		}
+	}
+}
+
+/// How the opacity of each layer decreases as more layers are painted, so later layers don't cover earlier ones
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpacityDecay {
+	None,
+	/// Divided by the number of layers painted so far (including this one), like a running average
+	Average,
+	/// Multiplied by a factor once for each layer painted before it
+	Factor(f64),
+}
+
+impl OpacityDecay {
+	/// Gets the opacity of a layer, from the opacity picked for it and its index in the layers painted.
+	pub fn apply(&self, opacity: f32, layer_index: usize) -> f32 {
+		match self {
+			Self::None => opacity,
+			Self::Average => opacity / (layer_index + 1) as f32,
+			Self::Factor(factor) => opacity * factor.powi(layer_index as i32) as f32,
+		}
+	}
+}
+
+/// How the blending mode of each layer is picked from the ones given
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum BlendOrder {
+	#[strum(serialize = "weighted-random")]
+	WeightedRandom,
+	#[strum(serialize = "cycle")]
+	Cycle,
+	#[strum(serialize = "shuffle")]
+	Shuffle,
 }
 
 impl BlendingMode {
+	/// The modes in a named group of modes with a similar effect, if it's a group name
+	pub fn from_group(name: &str) -> Option<Vec<BlendingMode>> {
+		match name {
+			"darkening" => Some(vec![Self::Multiply, Self::Darken, Self::ColorBurn, Self::LinearBurn]),
+			"lightening" => Some(vec![Self::Screen, Self::Lighten, Self::ColorDodge, Self::LinearDodge]),
+			_ => None,
+		}
+	}
+
+	/// Whether this mode can be computed one channel at a time. Non-separable modes need the whole
+	/// pixel, and must be blended with `blend_rgb()` instead
+	#[inline(always)]
+	pub fn is_separable(&self) -> bool {
+		!matches!(
+			self,
+			Self::Hue
+				| Self::Saturation
+				| Self::Color
+				| Self::Luminosity
+				| Self::DarkerColor
+				| Self::LighterColor
+		)
+	}
+
 	#[inline(always)]
 	pub fn blend(&self, bottom: f32, top: f32) -> f32 {
 		match self {
@@ -85,6 +198,83 @@ impl BlendingMode {
 			}
 			Self::Difference => (bottom - top).abs().max(0.0).min(1.0),
 			Self::Exclusion => bottom + top - 2.0 * bottom * top,
+			Self::LinearDodge => (bottom + top).min(1.0),
+			Self::LinearBurn => (bottom + top - 1.0).max(0.0),
+			Self::VividLight => {
+				// Color burn on the lower half, color dodge on the upper half; the guards are inherited
+				if top < 0.5 {
+					Self::ColorBurn.blend(bottom, 2.0 * top)
+				} else {
+					Self::ColorDodge.blend(bottom, 2.0 * top - 1.0)
+				}
+			}
+			Self::LinearLight => (bottom + 2.0 * top - 1.0).clamp(0.0, 1.0),
+			Self::PinLight => {
+				if top < 0.5 {
+					bottom.min(2.0 * top)
+				} else {
+					bottom.max(2.0 * top - 1.0)
+				}
+			}
+			Self::Subtract => (bottom - top).max(0.0),
+			Self::Divide => {
+				if top == 0.0 {
+					1.0
+				} else {
+					(bottom / top).min(1.0)
+				}
+			}
+			Self::Reflect => {
+				if top == 1.0 {
+					1.0
+				} else {
+					(bottom * bottom / (1.0 - top)).min(1.0)
+				}
+			}
+			Self::Glow => Self::Reflect.blend(top, bottom),
+			Self::Negation => 1.0 - (1.0 - bottom - top).abs(),
+			Self::Average => (bottom + top) / 2.0,
+			// Non-separable modes can't be computed per channel; see `blend_rgb()`
+			Self::Hue
+			| Self::Saturation
+			| Self::Color
+			| Self::Luminosity
+			| Self::DarkerColor
+			| Self::LighterColor => top,
+		}
+	}
+
+	/// Blends a whole pixel. This is needed for non-separable modes (based on hue, saturation,
+	/// luminosity, or comparing whole colors); other modes are just blended one channel at a time
+	/// More info: https://www.w3.org/TR/compositing-1/#blendingnonseparable
+	#[inline(always)]
+	pub fn blend_rgb(&self, bottom: [f32; 3], top: [f32; 3], weights: &Luma) -> [f32; 3] {
+		match self {
+			Self::Hue => {
+				set_luminosity(set_saturation(top, saturation(bottom)), luma(bottom, weights), weights)
+			}
+			Self::Saturation => {
+				set_luminosity(set_saturation(bottom, saturation(top)), luma(bottom, weights), weights)
+			}
+			Self::Color => set_luminosity(top, luma(bottom, weights), weights),
+			Self::Luminosity => set_luminosity(bottom, luma(top, weights), weights),
+			Self::DarkerColor => {
+				if luma(top, weights) < luma(bottom, weights) {
+					top
+				} else {
+					bottom
+				}
+			}
+			Self::LighterColor => {
+				if luma(top, weights) > luma(bottom, weights) {
+					top
+				} else {
+					bottom
+				}
+			}
+			_ => {
+				[self.blend(bottom[0], top[0]), self.blend(bottom[1], top[1]), self.blend(bottom[2], top[2])]
+			}
 		}
 	}
 
@@ -107,19 +297,111 @@ impl Default for BlendingMode {
 	}
 }
 
+/// Perceived brightness of a color, with the weights of each channel. It's also the luminosity used by the
+/// non-separable blending modes (`hue`, `saturation`, `color`, and `luminosity`)
 #[inline(always)]
-pub fn blend_pixel(bottom: &[f32], top: &[f32], opacity: f32, blending_mode: &BlendingMode) -> [f32; 3] {
-	if opacity == 0.0 {
+pub fn luma(color: [f32; 3], weights: &Luma) -> f32 {
+	let weights = weights.weights();
+	weights[0] * color[0] + weights[1] * color[1] + weights[2] * color[2]
+}
+
+#[inline(always)]
+fn saturation(color: [f32; 3]) -> f32 {
+	color[0].max(color[1]).max(color[2]) - color[0].min(color[1]).min(color[2])
+}
+
+/// Brings a color back into the 0-1 range while preserving its luminosity
+#[inline(always)]
+fn clip_color(color: [f32; 3], weights: &Luma) -> [f32; 3] {
+	let l = luma(color, weights);
+	let min = color[0].min(color[1]).min(color[2]);
+	let max = color[0].max(color[1]).max(color[2]);
+	let mut result = color;
+	if min < 0.0 {
+		result = result.map(|c| l + (c - l) * l / (l - min));
+	}
+	if max > 1.0 {
+		result = result.map(|c| l + (c - l) * (1.0 - l) / (max - l));
+	}
+	result
+}
+
+#[inline(always)]
+fn set_luminosity(color: [f32; 3], l: f32, weights: &Luma) -> [f32; 3] {
+	let d = l - luma(color, weights);
+	clip_color(color.map(|c| c + d), weights)
+}
+
+#[inline(always)]
+fn set_saturation(color: [f32; 3], s: f32) -> [f32; 3] {
+	let min = color[0].min(color[1]).min(color[2]);
+	let max = color[0].max(color[1]).max(color[2]);
+	if max > min {
+		color.map(|c| (c - min) * s / (max - min))
+	} else {
+		[0.0, 0.0, 0.0]
+	}
+}
+
+/// Blends a pixel, with a separate opacity for each channel
+#[inline(always)]
+pub fn blend_pixel(
+	bottom: &[f32],
+	top: &[f32],
+	opacity: [f32; 3],
+	blending_mode: &BlendingMode,
+	luma: &Luma,
+) -> [f32; 3] {
+	if opacity == [0.0, 0.0, 0.0] {
 		[bottom[0], bottom[1], bottom[2]]
+	} else if blending_mode.is_separable() {
+		[
+			blending_mode.blend_with_opacity(bottom[0], top[0], opacity[0]),
+			blending_mode.blend_with_opacity(bottom[1], top[1], opacity[1]),
+			blending_mode.blend_with_opacity(bottom[2], top[2], opacity[2]),
+		]
 	} else {
+		let bottom = [bottom[0], bottom[1], bottom[2]];
+		let opaque_result = blending_mode.blend_rgb(bottom, [top[0], top[1], top[2]], luma);
 		[
-			blending_mode.blend_with_opacity(bottom[0], top[0], opacity),
-			blending_mode.blend_with_opacity(bottom[1], top[1], opacity),
-			blending_mode.blend_with_opacity(bottom[2], top[2], opacity),
+			opaque_result[0] * opacity[0] + bottom[0] * (1.0 - opacity[0]),
+			opaque_result[1] * opacity[1] + bottom[1] * (1.0 - opacity[1]),
+			opaque_result[2] * opacity[2] + bottom[2] * (1.0 - opacity[2]),
 		]
 	}
 }
 
+/// Blends a pixel on top of a pixel that might be transparent, accumulating the coverage in the alpha.
+/// When the bottom pixel is opaque, this is the same as `blend_pixel()`
+/// More info: https://www.w3.org/TR/compositing-1/#blending
+#[inline(always)]
+pub fn blend_pixel_with_alpha(
+	bottom: &[f32],
+	bottom_alpha: f32,
+	top: &[f32],
+	opacity: [f32; 3],
+	blending_mode: &BlendingMode,
+	luma: &Luma,
+) -> ([f32; 3], f32) {
+	if bottom_alpha >= 1.0 {
+		return (blend_pixel(bottom, top, opacity, blending_mode, luma), 1.0);
+	}
+
+	// Where the bottom is transparent, the top color is used as-is rather than blended
+	let blended = blend_pixel(bottom, top, [1.0, 1.0, 1.0], blending_mode, luma);
+	let color = [0, 1, 2].map(|c| {
+		let mixed = (1.0 - bottom_alpha) * top[c] + bottom_alpha * blended[c];
+		let alpha = opacity[c] + bottom_alpha * (1.0 - opacity[c]);
+		if alpha == 0.0 {
+			bottom[c]
+		} else {
+			(mixed * opacity[c] + bottom[c] * bottom_alpha * (1.0 - opacity[c])) / alpha
+		}
+	});
+	let opacity = (opacity[0] + opacity[1] + opacity[2]) / 3.0;
+	(color, 1.0 - (1.0 - bottom_alpha) * (1.0 - opacity))
+}
+
 #[inline(always)]
 pub fn channel_u8_to_f32(color: u8) -> f32 {
 	color as f32 / 255.0
@@ -129,3 +411,123 @@ pub fn channel_u8_to_f32(color: u8) -> f32 {
 pub fn pixel_u8_to_f32(colors: &[u8; 3]) -> [f32; 3] {
 	[channel_u8_to_f32(colors[0]), channel_u8_to_f32(colors[1]), channel_u8_to_f32(colors[2])]
 }
+
+/// Decodes an sRGB-encoded channel value (0-1) into linear light
+/// More info: https://en.wikipedia.org/wiki/SRGB#Transformation
+#[inline(always)]
+pub fn srgb_to_linear(value: f32) -> f32 {
+	if value <= 0.04045 {
+		value / 12.92
+	} else {
+		((value + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+/// Encodes a linear light channel value (0-1) back into sRGB
+#[inline(always)]
+pub fn linear_to_srgb(value: f32) -> f32 {
+	if value <= 0.0031308 {
+		value * 12.92
+	} else {
+		1.055 * value.powf(1.0 / 2.4) - 0.055
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn divide_by_zero_is_white() {
+		assert_eq!(BlendingMode::Divide.blend(0.0, 0.0), 1.0);
+		assert_eq!(BlendingMode::Divide.blend(0.5, 0.0), 1.0);
+		assert_eq!(BlendingMode::Divide.blend(0.25, 0.5), 0.5);
+		assert_eq!(BlendingMode::Divide.blend(0.75, 0.5), 1.0);
+	}
+
+	#[test]
+	fn subtract_clamps_at_zero() {
+		assert_eq!(BlendingMode::Subtract.blend(0.25, 0.75), 0.0);
+		assert_eq!(BlendingMode::Subtract.blend(0.0, 1.0), 0.0);
+		assert_eq!(BlendingMode::Subtract.blend(0.75, 0.25), 0.5);
+	}
+
+	#[test]
+	fn reflect_and_glow_saturate_at_white() {
+		// Reflect divides by (1 - top), and glow by (1 - bottom)
+		assert_eq!(BlendingMode::Reflect.blend(0.5, 1.0), 1.0);
+		assert_eq!(BlendingMode::Reflect.blend(0.0, 1.0), 1.0);
+		assert_eq!(BlendingMode::Reflect.blend(1.0, 0.0), 1.0);
+		assert_eq!(BlendingMode::Reflect.blend(0.5, 0.5), 0.5);
+		assert_eq!(BlendingMode::Glow.blend(1.0, 0.5), 1.0);
+		assert_eq!(BlendingMode::Glow.blend(1.0, 0.0), 1.0);
+		assert_eq!(BlendingMode::Glow.blend(0.0, 1.0), 1.0);
+		assert_eq!(BlendingMode::Glow.blend(0.5, 0.5), 0.5);
+		for value in [0.0, 0.25, 0.5, 0.75, 1.0] {
+			assert!(BlendingMode::Reflect.blend(value, 1.0).is_finite());
+			assert!(BlendingMode::Glow.blend(1.0, value).is_finite());
+		}
+	}
+
+	#[test]
+	fn average_is_the_mean() {
+		assert_eq!(BlendingMode::Average.blend(0.0, 1.0), 0.5);
+		assert_eq!(BlendingMode::Average.blend(0.25, 0.75), 0.5);
+		assert_eq!(BlendingMode::Average.blend(0.5, 0.5), 0.5);
+		assert_eq!(BlendingMode::Average.blend(1.0, 1.0), 1.0);
+		assert_eq!(BlendingMode::Average.blend_with_opacity(0.0, 1.0, 0.5), 0.25);
+	}
+
+	#[test]
+	fn hue_keeps_bottom_saturation_and_luminosity() {
+		let bottom = [0.6, 0.4, 0.2];
+		let top = [0.1, 0.2, 0.9];
+		for weights in [Luma::Rec709, Luma::Rec601, Luma::Average] {
+			let result = BlendingMode::Hue.blend_rgb(bottom, top, &weights);
+			assert!((saturation(result) - saturation(bottom)).abs() < 1e-5);
+			assert!((luma(result, &weights) - luma(bottom, &weights)).abs() < 1e-5);
+			// The hue comes from the top: blue is still the strongest channel, and red the weakest
+			assert!(result[2] > result[1] && result[1] > result[0]);
+		}
+	}
+
+	#[test]
+	fn negation_is_difference_with_inverted_bottom() {
+		let values = [0.0, 0.125, 0.25, 0.5, 0.75, 1.0];
+		for bottom in values {
+			for top in values {
+				let negation = BlendingMode::Negation.blend(bottom, top);
+				assert_eq!(negation, 1.0 - BlendingMode::Difference.blend(1.0 - bottom, top));
+				assert!((0.0..=1.0).contains(&negation));
+			}
+			// Over black, both leave the bottom unchanged
+			assert_eq!(
+				BlendingMode::Negation.blend(bottom, 0.0),
+				BlendingMode::Difference.blend(bottom, 0.0)
+			);
+		}
+		// Unlike difference, light colors over themselves don't turn black
+		assert_eq!(BlendingMode::Negation.blend(0.75, 0.75), 0.5);
+		assert_eq!(BlendingMode::Difference.blend(0.75, 0.75), 0.0);
+	}
+
+	#[test]
+	fn darker_and_lighter_color_keep_the_hue() {
+		// The whole color with the lower (or higher) luminosity is used, instead of picking each channel like
+		// `darken` and `lighten`, so no new hues are made
+		let bottom = [0.9, 0.2, 0.1];
+		let top = [0.3, 0.4, 0.5];
+		assert_eq!(BlendingMode::DarkerColor.blend_rgb(bottom, top, &Luma::Rec709), bottom);
+		assert_eq!(BlendingMode::LighterColor.blend_rgb(bottom, top, &Luma::Rec709), top);
+		assert_eq!(BlendingMode::Darken.blend_rgb(bottom, top, &Luma::Rec709), [0.3, 0.2, 0.1]);
+	}
+
+	#[test]
+	fn darker_color_uses_luma_weights() {
+		let (red, green) = ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+		assert_eq!(BlendingMode::DarkerColor.blend_rgb(green, red, &Luma::Rec709), red);
+		assert_eq!(BlendingMode::LighterColor.blend_rgb(green, red, &Luma::Rec709), green);
+		// With the same weight for each channel, neither is darker, so the bottom is kept
+		assert_eq!(BlendingMode::DarkerColor.blend_rgb(green, red, &Luma::Average), green);
+	}
+}
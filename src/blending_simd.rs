@@ -0,0 +1,75 @@
+use wide::f32x4;
+
+use crate::blending::BlendingMode;
+
+/// Whether a mode can be blended 4 pixels at a time with `blend_pixels_x4()`. Only common modes without
+/// branches are supported; all others have to be blended one pixel at a time
+#[inline(always)]
+pub fn is_supported(blending_mode: &BlendingMode) -> bool {
+	matches!(
+		blending_mode,
+		BlendingMode::Normal | BlendingMode::Multiply | BlendingMode::Screen | BlendingMode::LinearDodge
+	)
+}
+
+/// Blends 4 opaque pixels at once, one channel at a time, with a separate opacity for each channel of each
+/// pixel. Gives the same results as `blend_pixel()`, and only supports modes that pass `is_supported()`
+#[inline(always)]
+pub fn blend_pixels_x4(
+	bottom: [[f32; 3]; 4],
+	top: [[f32; 3]; 4],
+	opacity: [[f32; 3]; 4],
+	blending_mode: &BlendingMode,
+) -> [[f32; 3]; 4] {
+	let one = f32x4::splat(1.0);
+	let channels = [0, 1, 2].map(|c| {
+		let bottom = f32x4::from([bottom[0][c], bottom[1][c], bottom[2][c], bottom[3][c]]);
+		let top = f32x4::from([top[0][c], top[1][c], top[2][c], top[3][c]]);
+		let opacity = f32x4::from([opacity[0][c], opacity[1][c], opacity[2][c], opacity[3][c]]);
+		let opaque_result = match blending_mode {
+			BlendingMode::Normal => top,
+			BlendingMode::Multiply => bottom * top,
+			BlendingMode::Screen => one - (one - bottom) * (one - top),
+			BlendingMode::LinearDodge => (bottom + top).min(one),
+			_ => unreachable!("blending mode not supported with SIMD"),
+		};
+		(opaque_result * opacity + bottom * (one - opacity)).to_array()
+	});
+	[0, 1, 2, 3].map(|p| [channels[0][p], channels[1][p], channels[2][p]])
+}
+
+#[cfg(all(test, feature = "simd"))]
+mod tests {
+	use super::*;
+	use crate::blending::{Luma, blend_pixel};
+
+	#[test]
+	fn same_as_scalar() {
+		let values = [0u8, 1, 63, 127, 128, 200, 254, 255].map(|value| value as f32 / 255.0);
+		for blending_mode in
+			[BlendingMode::Normal, BlendingMode::Multiply, BlendingMode::Screen, BlendingMode::LinearDodge]
+		{
+			for bottom in values {
+				for top in values {
+					let bottoms = [[bottom, top, 0.5], [top, bottom, 0.25], [bottom; 3], [top; 3]];
+					let tops = [[top, bottom, 0.75], [bottom, top, 1.0], [top; 3], [bottom; 3]];
+					let opacities = [[1.0; 3], [0.5, 0.25, 0.0], [0.0; 3], [bottom, top, 0.5]];
+					let simd = blend_pixels_x4(bottoms, tops, opacities, &blending_mode);
+					for p in 0..4 {
+						let scalar =
+							blend_pixel(&bottoms[p], &tops[p], opacities[p], &blending_mode, &Luma::Rec709);
+						for c in 0..3 {
+							let (simd, scalar) = ((simd[p][c] * 255.0).round(), (scalar[c] * 255.0).round());
+							assert!(
+								(simd - scalar).abs() <= 1.0,
+								"{blending_mode} of {:?} over {:?}: {simd} instead of {scalar}",
+								tops[p],
+								bottoms[p]
+							);
+						}
+					}
+				}
+			}
+		}
+	}
+}
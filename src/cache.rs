@@ -0,0 +1,165 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use rust_faces::{Face, Rect};
+use serde::{Deserialize, Serialize};
+
+// Version of the cache files (version 1 was plain text); files saved by other versions are ignored, and
+// overwritten when saving
+const CACHE_VERSION: u32 = 2;
+
+/// What identifies the current version of a file, and the settings used to detect its faces. If any of it
+/// changes, cached results are not used anymore
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct CacheKey {
+	path: PathBuf,
+	size: u64,
+	modified_secs: u64,
+	modified_nanos: u32,
+	settings: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct CachedFace {
+	confidence: f32,
+	rect: [f32; 4],
+	landmarks: Option<Vec<(f32, f32)>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheFile {
+	version: u32,
+	key: CacheKey,
+	faces: Vec<CachedFace>,
+}
+
+/**
+ * Get the key of a file, with the settings used to detect its faces
+ */
+fn get_key(path: &Path, settings: &str) -> Option<CacheKey> {
+	let path = path.canonicalize().ok()?;
+	let metadata = fs::metadata(&path).ok()?;
+	let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+	Some(CacheKey {
+		path,
+		size: metadata.len(),
+		modified_secs: modified.as_secs(),
+		modified_nanos: modified.subsec_nanos(),
+		settings: settings.to_string(),
+	})
+}
+
+/**
+ * Get the cache file for a file; the name is a 64-bit FNV-1a hash of its canonical path
+ */
+fn get_cache_file(cache_dir: &Path, key: &CacheKey) -> PathBuf {
+	let hash = key
+		.path
+		.as_os_str()
+		.as_encoded_bytes()
+		.iter()
+		.fold(0xcbf29ce484222325u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+	cache_dir.join(format!("{:016x}.json", hash))
+}
+
+/**
+ * Read the faces detected in a file from the cache, if they were saved for the same version of the file and
+ * the same settings
+ */
+pub fn load_faces(cache_dir: &Path, path: &Path, settings: &str) -> Option<Vec<Face>> {
+	let key = get_key(path, settings)?;
+	let contents = fs::read_to_string(get_cache_file(cache_dir, &key)).ok()?;
+	let cache_file: CacheFile = serde_json::from_str(&contents).ok()?;
+	if cache_file.version != CACHE_VERSION || cache_file.key != key {
+		return None;
+	}
+	Some(cache_file.faces.into_iter().map(to_face).collect())
+}
+
+/**
+ * Save the faces detected in a file to the cache. Failing to save is not an error, since the faces can still
+ * be detected again next time
+ */
+pub fn save_faces(cache_dir: &Path, path: &Path, settings: &str, faces: &[Face]) {
+	if let Some(key) = get_key(path, settings) {
+		let cache_path = get_cache_file(cache_dir, &key);
+		let cache_file = CacheFile {
+			version: CACHE_VERSION,
+			key,
+			faces: faces.iter().map(from_face).collect(),
+		};
+		if let Ok(contents) = serde_json::to_string(&cache_file) {
+			fs::write(cache_path, contents).ok();
+		}
+	}
+}
+
+fn from_face(face: &Face) -> CachedFace {
+	CachedFace {
+		confidence: face.confidence,
+		rect: [face.rect.x, face.rect.y, face.rect.width, face.rect.height],
+		landmarks: face.landmarks.clone(),
+	}
+}
+
+fn to_face(face: CachedFace) -> Face {
+	Face {
+		confidence: face.confidence,
+		rect: Rect {
+			x: face.rect[0],
+			y: face.rect[1],
+			width: face.rect[2],
+			height: face.rect[3],
+		},
+		landmarks: face.landmarks,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn face(confidence: f32, landmarks: Option<Vec<(f32, f32)>>) -> Face {
+		Face {
+			confidence,
+			rect: Rect {
+				x: 10.0,
+				y: 20.5,
+				width: 30.0,
+				height: 40.25,
+			},
+			landmarks,
+		}
+	}
+
+	#[test]
+	fn save_and_load() {
+		let dir = std::env::temp_dir().join(format!("face-stack-cache-test-{}", std::process::id()));
+		let cache_dir = dir.join("cache");
+		fs::create_dir_all(&cache_dir).unwrap();
+		// Paths can have any character, including the separators of other formats
+		let path = dir.join("photo\twith \"tabs\", quotes\nand lines.jpg");
+		fs::write(&path, "not really an image").unwrap();
+
+		let faces = [face(0.9, Some(vec![(1.0, 2.0), (3.0, 4.0)])), face(0.5, None)];
+		save_faces(&cache_dir, &path, "blazeface 1280", &faces);
+		let loaded = load_faces(&cache_dir, &path, "blazeface 1280").unwrap();
+		assert_eq!(
+			loaded.iter().map(from_face).collect::<Vec<_>>(),
+			faces.iter().map(from_face).collect::<Vec<_>>()
+		);
+
+		// Other settings, or a cache file from another version, are ignored
+		assert!(load_faces(&cache_dir, &path, "mtcnn 1280").is_none());
+		let cache_path = get_cache_file(&cache_dir, &get_key(&path, "").unwrap());
+		let contents = fs::read_to_string(&cache_path).unwrap();
+		fs::write(&cache_path, contents.replace(&format!("\"version\":{CACHE_VERSION}"), "\"version\":1"))
+			.unwrap();
+		assert!(load_faces(&cache_dir, &path, "blazeface 1280").is_none());
+		fs::write(&cache_path, "not json").unwrap();
+		assert!(load_faces(&cache_dir, &path, "blazeface 1280").is_none());
+
+		fs::remove_dir_all(&dir).ok();
+	}
+}
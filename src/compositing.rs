@@ -0,0 +1,248 @@
+use image::{Rgba32FImage, RgbaImage};
+
+#[cfg(feature = "simd")]
+use crate::blending_simd;
+use crate::{
+	blending::{
+		BlendingMode, Luma, blend_pixel_with_alpha, channel_u8_to_f32, pixel_u8_to_f32, srgb_to_linear,
+	},
+	geom::{WHi, XYWHi, XYi, intersect},
+	masking::Mask,
+	stacking::Stack,
+};
+
+/**
+ * Find the area of the bottom image that a top image would be painted on, if any
+ */
+pub fn paintable_rect(
+	bottom_size: WHi,
+	top_size: WHi,
+	top_offset: XYi,
+	mask: Option<XYWHi>,
+) -> Option<XYWHi> {
+	// Find paintable intersection between bottom and top
+	let bottom_rect = (0, 0, bottom_size.0, bottom_size.1);
+	let top_rect = (top_offset.0, top_offset.1, top_size.0, top_size.1);
+	let intersection = intersect(bottom_rect, top_rect)?;
+
+	// Applies further intersection if a mask is present
+	match mask {
+		Some(mask) => intersect(intersection, mask),
+		None => Some(intersection),
+	}
+}
+
+/**
+ * Copy one image on top of another. If `linear_light` is set, the bottom image is expected to be in
+ * linear light already, and the top image is converted from sRGB before blending. The bottom image's alpha
+ * accumulates the coverage of all images blended on it
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn blend_image(
+	bottom: &mut Rgba32FImage,
+	top: &RgbaImage,
+	top_offset: XYi,
+	opacity: [f32; 3],
+	blending_mode: &BlendingMode,
+	luma: &Luma,
+	mask: Option<&Mask>,
+	linear_light: bool,
+) {
+	let intersection =
+		paintable_rect(bottom.dimensions(), top.dimensions(), top_offset, mask.map(|mask| mask.rect));
+	if intersection.is_none() {
+		// Nothing to paint, either because the top image is outside the bottom one, or after masking
+		return;
+	}
+	let intersection_rect = intersection.unwrap();
+
+	let dst_x1 = intersection_rect.0;
+	let dst_y1 = intersection_rect.1;
+	let dst_x2 = intersection_rect.0 + intersection_rect.2 as i32; // Exclusive
+	let dst_y2 = intersection_rect.1 + intersection_rect.3 as i32; // Exclusive
+
+	let top_pixel_at = |dst_x: i32, dst_y: i32| -> [u8; 4] {
+		top.get_pixel((dst_x - top_offset.0) as u32, (dst_y - top_offset.1) as u32).0
+	};
+	let top_color_at = |dst_x: i32, dst_y: i32| -> [f32; 3] {
+		let top_px = top_pixel_at(dst_x, dst_y);
+		let top_px = pixel_u8_to_f32(&[top_px[0], top_px[1], top_px[2]]);
+		if linear_light {
+			top_px.map(srgb_to_linear)
+		} else {
+			top_px
+		}
+	};
+	// The alpha of the top image works like a mask
+	let opacity_at = |dst_x: i32, dst_y: i32| -> [f32; 3] {
+		let top_opacity = channel_u8_to_f32(top_pixel_at(dst_x, dst_y)[3]);
+		let mask_opacity = mask.map_or(1.0, |mask| mask.opacity_at(dst_x, dst_y));
+		opacity.map(|value| value * top_opacity * mask_opacity)
+	};
+
+	for dst_y in dst_y1..dst_y2 {
+		let mut dst_x = dst_x1;
+		while dst_x < dst_x2 {
+			// Blend 4 pixels at once when possible; only opaque pixels are supported
+			#[cfg(feature = "simd")]
+			if dst_x + 4 <= dst_x2 && blending_simd::is_supported(blending_mode) {
+				let xs = [0, 1, 2, 3].map(|i| dst_x + i);
+				let bottom_pxs = xs.map(|x| bottom.get_pixel(x as u32, dst_y as u32).0);
+				if bottom_pxs.iter().all(|px| px[3] >= 1.0) {
+					let blended = blending_simd::blend_pixels_x4(
+						bottom_pxs.map(|px| [px[0], px[1], px[2]]),
+						xs.map(|x| top_color_at(x, dst_y)),
+						xs.map(|x| opacity_at(x, dst_y)),
+						blending_mode,
+					);
+					for (x, color) in xs.into_iter().zip(blended) {
+						bottom.get_pixel_mut(x as u32, dst_y as u32).0 = [color[0], color[1], color[2], 1.0];
+					}
+					dst_x += 4;
+					continue;
+				}
+			}
+
+			let bottom_px = bottom.get_pixel_mut(dst_x as u32, dst_y as u32);
+			let (blended, alpha) = blend_pixel_with_alpha(
+				&bottom_px.0[0..3],
+				bottom_px.0[3],
+				&top_color_at(dst_x, dst_y),
+				opacity_at(dst_x, dst_y),
+				blending_mode,
+				luma,
+			);
+			bottom_px.0 = [blended[0], blended[1], blended[2], alpha];
+			dst_x += 1;
+		}
+	}
+}
+
+/**
+ * Add one image to a stack, to be combined with all other layers at the end. Since stacks ignore opacity, the
+ * mask and the alpha of the image only decide which pixels are used
+ */
+pub fn stack_image(stack: &mut Stack, top: &RgbaImage, top_offset: XYi, mask: Option<&Mask>) {
+	let intersection =
+		paintable_rect(stack.dimensions(), top.dimensions(), top_offset, mask.map(|mask| mask.rect));
+	if intersection.is_none() {
+		// Nothing to paint, either because the top image is outside the bottom one, or after masking
+		return;
+	}
+	let intersection_rect = intersection.unwrap();
+
+	let dst_x1 = intersection_rect.0;
+	let dst_y1 = intersection_rect.1;
+	let dst_x2 = intersection_rect.0 + intersection_rect.2 as i32; // Exclusive
+	let dst_y2 = intersection_rect.1 + intersection_rect.3 as i32; // Exclusive
+
+	for dst_y in dst_y1..dst_y2 {
+		let src_y = (dst_y - top_offset.1) as u32;
+		for dst_x in dst_x1..dst_x2 {
+			if mask.is_some_and(|mask| mask.opacity_at(dst_x, dst_y) <= 0.0) {
+				continue;
+			}
+			let top_px = top.get_pixel((dst_x - top_offset.0) as u32, src_y).0;
+			if top_px[3] == 0 {
+				continue;
+			}
+			stack.add_pixel(dst_x as u32, dst_y as u32, &[top_px[0], top_px[1], top_px[2]]);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use image::Rgba;
+
+	use super::*;
+
+	/// Blends a solid white image on a solid black one, checking that exactly the intersection is painted
+	fn assert_blends_solid(bottom_size: WHi, top_size: WHi, top_offset: XYi, background_alpha: f32) {
+		let mut bottom =
+			Rgba32FImage::from_pixel(bottom_size.0, bottom_size.1, Rgba([0.0, 0.0, 0.0, background_alpha]));
+		let top = RgbaImage::from_pixel(top_size.0, top_size.1, Rgba([255, 255, 255, 255]));
+		blend_image(
+			&mut bottom,
+			&top,
+			top_offset,
+			[1.0, 1.0, 1.0],
+			&BlendingMode::Normal,
+			&Luma::Rec709,
+			None,
+			false,
+		);
+
+		let (x, y, width, height) = paintable_rect(bottom_size, top_size, top_offset, None).unwrap();
+		for (px, py, pixel) in bottom.enumerate_pixels() {
+			let (px, py) = (px as i32, py as i32);
+			let inside = px >= x && px < x + width as i32 && py >= y && py < y + height as i32;
+			let expected = if inside {
+				[1.0, 1.0, 1.0, 1.0]
+			} else {
+				[0.0, 0.0, 0.0, background_alpha]
+			};
+			assert_eq!(pixel.0, expected, "pixel {px},{py} with the top image at {top_offset:?}");
+		}
+	}
+
+	#[test]
+	fn blend_writes_whole_intersection() {
+		// Inside, with a width that isn't a multiple of 4 pixels
+		assert_blends_solid((10, 6), (7, 4), (2, 1), 1.0);
+		// Covering the last row and column
+		assert_blends_solid((10, 6), (4, 3), (6, 3), 1.0);
+		// Partially outside each edge
+		assert_blends_solid((10, 6), (5, 4), (-2, -1), 1.0);
+		assert_blends_solid((10, 6), (5, 4), (8, 4), 1.0);
+		// Larger than the bottom image
+		assert_blends_solid((10, 6), (14, 10), (-2, -2), 1.0);
+		// On a transparent background
+		assert_blends_solid((10, 6), (7, 4), (2, 1), 0.0);
+	}
+
+	#[test]
+	fn blend_outside_does_nothing() {
+		let mut bottom = Rgba32FImage::from_pixel(10, 6, Rgba([0.0, 0.0, 0.0, 1.0]));
+		let top = RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+		blend_image(
+			&mut bottom,
+			&top,
+			(10, 0),
+			[1.0, 1.0, 1.0],
+			&BlendingMode::Normal,
+			&Luma::Rec709,
+			None,
+			false,
+		);
+		blend_image(
+			&mut bottom,
+			&top,
+			(-4, -4),
+			[1.0, 1.0, 1.0],
+			&BlendingMode::Normal,
+			&Luma::Rec709,
+			None,
+			false,
+		);
+		assert!(bottom.pixels().all(|pixel| pixel.0 == [0.0, 0.0, 0.0, 1.0]));
+	}
+
+	#[test]
+	fn blend_uses_top_alpha() {
+		let mut bottom = Rgba32FImage::from_pixel(4, 1, Rgba([0.0, 0.0, 0.0, 1.0]));
+		let top = RgbaImage::from_fn(4, 1, |x, _| Rgba([255, 255, 255, [0, 51, 255, 0][x as usize]]));
+		blend_image(
+			&mut bottom,
+			&top,
+			(0, 0),
+			[1.0, 1.0, 1.0],
+			&BlendingMode::Normal,
+			&Luma::Rec709,
+			None,
+			false,
+		);
+		let colors = bottom.pixels().map(|pixel| pixel.0[0]).collect::<Vec<f32>>();
+		assert_eq!(colors, [0.0, 0.2, 1.0, 0.0]);
+	}
+}
@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// A value in a configuration file: a switch, or one or more values for an option
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ConfigValue {
+	Flag(bool),
+	Integer(i64),
+	Float(f64),
+	Text(String),
+	List(Vec<ConfigValue>),
+}
+
+impl ConfigValue {
+	/// The value as it would be written in the command line
+	fn to_text(&self) -> Option<String> {
+		match self {
+			Self::Integer(value) => Some(value.to_string()),
+			Self::Float(value) => Some(value.to_string()),
+			Self::Text(value) => Some(value.clone()),
+			Self::Flag(_) | Self::List(_) => None,
+		}
+	}
+}
+
+/// Options read from a TOML configuration file, with the same names as the command line options (with "_" or
+/// "-", e.g. `crop_width`). Each option is merged into the options from the command line with one of the
+/// `merge_*()` methods, which parse its values like the command line does
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Config {
+	options: BTreeMap<String, ConfigValue>,
+}
+
+impl Config {
+	pub fn read(path: &Path) -> Result<Config, String> {
+		let contents =
+			fs::read_to_string(path).map_err(|err| format!("Could not read {:?}: {}", path, err))?;
+		Config::parse(&contents).map_err(|err| format!("{} in {:?}", err, path))
+	}
+
+	pub fn parse(contents: &str) -> Result<Config, String> {
+		let config: Config = toml::from_str(contents).map_err(|err| err.message().to_string())?;
+		// Names are normalized to the ones in the command line
+		let options =
+			config.options.into_iter().map(|(name, value)| (name.replace('_', "-"), value)).collect();
+		Ok(Config {
+			options,
+		})
+	}
+
+	/// Removes the options that shouldn't be used from the file (e.g. because they're in the command line)
+	pub fn remove_if(&mut self, should_remove: impl Fn(&str) -> bool) {
+		self.options.retain(|name, _| !should_remove(name));
+	}
+
+	/// Fails if there are options left that weren't merged, since they're not known
+	pub fn check_all_merged(&self) -> Result<(), String> {
+		match self.options.keys().next() {
+			Some(name) => Err(format!("Unknown option \"{}\"", name)),
+			None => Ok(()),
+		}
+	}
+
+	/// Merges a switch (e.g. `recursive = true`)
+	pub fn merge_flag(&mut self, name: &str, flag: &mut bool) -> Result<(), String> {
+		match self.options.remove(name) {
+			Some(ConfigValue::Flag(value)) => *flag = value,
+			Some(_) => return Err(format!("Option \"{}\" must be true or false", name)),
+			None => (),
+		}
+		Ok(())
+	}
+
+	/// Merges an option with a single value (e.g. `seed = 123`, or `output = "stack.png"`)
+	pub fn merge_value<T, E: Display>(
+		&mut self,
+		name: &str,
+		value: &mut T,
+		parse: impl Fn(&str) -> Result<T, E>,
+	) -> Result<(), String> {
+		if let Some(config_value) = self.options.remove(name) {
+			let text = config_value.to_text().ok_or(format!("Option \"{}\" must be a single value", name))?;
+			*value = parse(&text)
+				.map_err(|err| format!("Invalid value \"{}\" for \"{}\": {}", text, name, err))?;
+		}
+		Ok(())
+	}
+
+	/// Merges an option with a single value that's not required
+	pub fn merge_option<T, E: Display>(
+		&mut self,
+		name: &str,
+		value: &mut Option<T>,
+		parse: impl Fn(&str) -> Result<T, E>,
+	) -> Result<(), String> {
+		if self.options.contains_key(name) {
+			self.merge_value(name, value, |text| parse(text).map(Some))?;
+		}
+		Ok(())
+	}
+
+	/// Merges an option with a list of values, replacing the default ones. Values can be given as an array
+	/// (e.g. `opacity = ["0.5", "0.7-0.8@2"]`), or separated by commas, like in the command line
+	pub fn merge_values<T, E: Display>(
+		&mut self,
+		name: &str,
+		values: &mut Vec<T>,
+		parse: impl Fn(&str) -> Result<T, E>,
+	) -> Result<(), String> {
+		self.merge_items(name, values, |text| text.split(',').map(&parse).collect())
+	}
+
+	/// Like `merge_values()`, for options that need at least one value, like the ones with a default
+	pub fn merge_required_values<T, E: Display>(
+		&mut self,
+		name: &str,
+		values: &mut Vec<T>,
+		parse: impl Fn(&str) -> Result<T, E>,
+	) -> Result<(), String> {
+		self.merge_values(name, values, parse)?;
+		if values.is_empty() {
+			return Err(format!("Option \"{}\" needs at least one value", name));
+		}
+		Ok(())
+	}
+
+	/// Like `merge_values()`, but values aren't separated by commas, so each one can contain them
+	pub fn merge_list<T, E: Display>(
+		&mut self,
+		name: &str,
+		values: &mut Vec<T>,
+		parse: impl Fn(&str) -> Result<T, E>,
+	) -> Result<(), String> {
+		self.merge_items(name, values, |text| parse(text).map(|value| vec![value]))
+	}
+
+	fn merge_items<T, E: Display>(
+		&mut self,
+		name: &str,
+		values: &mut Vec<T>,
+		parse: impl Fn(&str) -> Result<Vec<T>, E>,
+	) -> Result<(), String> {
+		let items = match self.options.remove(name) {
+			Some(ConfigValue::List(items)) => items,
+			Some(item) => vec![item],
+			None => return Ok(()),
+		};
+		values.clear();
+		for item in items {
+			let text =
+				item.to_text().ok_or(format!("Option \"{}\" must be a value or a list of values", name))?;
+			values.extend(
+				parse(&text)
+					.map_err(|err| format!("Invalid value \"{}\" for \"{}\": {}", text, name, err))?,
+			);
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn merges_values() {
+		let mut config = Config::parse(
+			"# Comment\ninput = \"{a,b}.jpg\"\nseed = 123\nface_fill = 0.4\nrecursive = true\nopacity = [\"0.5\", \"0.7-0.8@2\", 1]\ncrop-width = \"10,20\"\n",
+		)
+		.unwrap();
+		let (mut seed, mut face_fill, mut recursive) = (0u64, 0.6f64, false);
+		let (mut opacity, mut crop_width) = (vec!["default".to_string()], Vec::<u32>::new());
+		let mut input = Vec::<String>::new();
+		config.merge_value("seed", &mut seed, str::parse).unwrap();
+		config.merge_value("face-fill", &mut face_fill, str::parse).unwrap();
+		config.merge_flag("recursive", &mut recursive).unwrap();
+		config.merge_list("input", &mut input, str::parse).unwrap();
+		config.merge_values("opacity", &mut opacity, str::parse).unwrap();
+		config.merge_values("crop-width", &mut crop_width, str::parse).unwrap();
+		assert_eq!((seed, face_fill, recursive), (123, 0.4, true));
+		assert_eq!(opacity, ["0.5", "0.7-0.8@2", "1"]);
+		assert_eq!(crop_width, [10, 20]);
+		assert_eq!(input, ["{a,b}.jpg"]);
+		assert!(config.check_all_merged().is_ok());
+	}
+
+	#[test]
+	fn keeps_values_not_in_file() {
+		let mut config = Config::parse("seed = 1\n").unwrap();
+		let mut input = vec!["*.jpg".to_string()];
+		let mut metadata: Option<String> = None;
+		config.merge_list("input", &mut input, str::parse).unwrap();
+		config.merge_option("metadata", &mut metadata, str::parse).unwrap();
+		assert_eq!(input, ["*.jpg"]);
+		assert_eq!(metadata, None);
+		assert_eq!(config.check_all_merged(), Err("Unknown option \"seed\"".to_string()));
+	}
+
+	#[test]
+	fn removed_options_are_not_merged() {
+		let mut config = Config::parse("seed = 1\nquiet = true\n").unwrap();
+		config.remove_if(|name| name == "seed");
+		let mut seed = 5u64;
+		config.merge_value("seed", &mut seed, str::parse).unwrap();
+		assert_eq!(seed, 5);
+	}
+
+	#[test]
+	fn invalid_values() {
+		let mut seed = 0u64;
+		let mut recursive = false;
+		assert!(Config::parse("seed = ").is_err());
+		assert!(Config::parse("seed = 1\nseed = 2").is_err());
+		assert!(
+			Config::parse("seed = \"a\"").unwrap().merge_value("seed", &mut seed, str::parse::<u64>).is_err()
+		);
+		assert!(
+			Config::parse("seed = [1, 2]")
+				.unwrap()
+				.merge_value("seed", &mut seed, str::parse::<u64>)
+				.is_err()
+		);
+		assert!(Config::parse("recursive = 1").unwrap().merge_flag("recursive", &mut recursive).is_err());
+		let mut size = vec![1024u32];
+		assert_eq!(
+			Config::parse("size = []").unwrap().merge_required_values("size", &mut size, str::parse::<u32>),
+			Err("Option \"size\" needs at least one value".to_string())
+		);
+	}
+}
@@ -0,0 +1,51 @@
+use image::{DynamicImage, ImageBuffer, Rgb, RgbImage, Rgba, RgbaImage, imageops};
+
+use crate::geom::{WHi, XYi, fit_inside, whf_to_whi};
+
+// Longest side of each cell in the sheet
+const CELL_SIZE: f32 = 256.0;
+
+const BACKGROUND_COLOR: Rgb<u8> = Rgb([0, 0, 0]);
+
+/// A grid of thumbnails of all the layers used, showing each one as it's positioned in the output image
+pub struct ContactSheet {
+	output_size: WHi,
+	cell_size: WHi,
+	cells: Vec<RgbImage>,
+}
+
+impl ContactSheet {
+	pub fn new(output_size: WHi) -> Self {
+		let cell_size = fit_inside((CELL_SIZE, CELL_SIZE), (output_size.0 as f32, output_size.1 as f32));
+		let cell_size = whf_to_whi(cell_size);
+		ContactSheet {
+			output_size,
+			cell_size: (cell_size.0.max(1), cell_size.1.max(1)),
+			cells: Vec::new(),
+		}
+	}
+
+	/// Adds a thumbnail of a layer, cropped to the area of the output image it covers.
+	pub fn add(&mut self, layer: &RgbaImage, offset: XYi) {
+		let background = Rgba([BACKGROUND_COLOR[0], BACKGROUND_COLOR[1], BACKGROUND_COLOR[2], 255]);
+		let mut cell = ImageBuffer::from_pixel(self.output_size.0, self.output_size.1, background);
+		imageops::overlay(&mut cell, layer, offset.0 as i64, offset.1 as i64);
+		let cell = DynamicImage::ImageRgba8(cell).into_rgb8();
+		self.cells.push(imageops::thumbnail(&cell, self.cell_size.0, self.cell_size.1));
+	}
+
+	/// Lays out all thumbnails in a grid, as square as possible, in the order they were added.
+	pub fn to_image(&self) -> RgbImage {
+		let count = self.cells.len().max(1) as u32;
+		let columns = (count as f32).sqrt().ceil() as u32;
+		let rows = count.div_ceil(columns);
+		let mut sheet =
+			ImageBuffer::from_pixel(columns * self.cell_size.0, rows * self.cell_size.1, BACKGROUND_COLOR);
+		for (index, cell) in self.cells.iter().enumerate() {
+			let x = (index as u32 % columns) * self.cell_size.0;
+			let y = (index as u32 / columns) * self.cell_size.1;
+			imageops::replace(&mut sheet, cell, x as i64, y as i64);
+		}
+		sheet
+	}
+}
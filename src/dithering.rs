@@ -0,0 +1,63 @@
+use image::Rgba32FImage;
+
+/// Quantizes the color channels (0-1) of an image to `levels` + 1 steps, diffusing the quantization error
+/// of each pixel to its neighbors with Floyd-Steinberg dithering. This avoids banding in smooth gradients
+/// when the image is later converted to a lower bit depth (e.g. with `levels` of 255 for 8 bits).
+/// More info: https://en.wikipedia.org/wiki/Floyd%E2%80%93Steinberg_dithering
+pub fn dither_floyd_steinberg(image: &mut Rgba32FImage, levels: f32) {
+	let (width, height) = image.dimensions();
+	for y in 0..height {
+		for x in 0..width {
+			let pixel = image.get_pixel_mut(x, y);
+			let mut errors = [0.0f32; 3];
+			for (channel, error) in pixel.0.iter_mut().take(3).zip(errors.iter_mut()) {
+				let quantized = ((*channel * levels).round() / levels).clamp(0.0, 1.0);
+				*error = *channel - quantized;
+				*channel = quantized;
+			}
+
+			let neighbors =
+				[(1i64, 0i64, 7.0 / 16.0), (-1, 1, 3.0 / 16.0), (0, 1, 5.0 / 16.0), (1, 1, 1.0 / 16.0)];
+			for (dx, dy, weight) in neighbors {
+				let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+				if nx < 0 || nx >= width as i64 || ny >= height as i64 {
+					continue;
+				}
+				let neighbor = image.get_pixel_mut(nx as u32, ny as u32);
+				for (channel, error) in neighbor.0.iter_mut().take(3).zip(errors) {
+					*channel += error * weight;
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn gradient_has_no_bands() {
+		// A shallow gradient between two 8-bit levels; without dithering, it'd be two flat bands of 128 pixels
+		let (width, height) = (256, 8);
+		let mut image = Rgba32FImage::from_fn(width, height, |x, _| {
+			let value = (1.25 + 0.5 * x as f32 / (width - 1) as f32) / 255.0;
+			image::Rgba([value, value, value, 1.0])
+		});
+		let original = image.clone();
+		dither_floyd_steinberg(&mut image, 255.0);
+
+		for y in 0..height {
+			let row =
+				(0..width).map(|x| (image.get_pixel(x, y).0[0] * 255.0).round() as u8).collect::<Vec<_>>();
+			assert!(row.iter().all(|&value| value == 1 || value == 2));
+			// The first row only gets errors from its left, so it mixes levels more slowly than the others
+			let longest_run = row.chunk_by(|a, b| a == b).map(|run| run.len()).max().unwrap();
+			assert!(longest_run <= 32, "row {y} has a band of {longest_run} pixels");
+		}
+
+		// The average brightness is kept
+		let sum = |image: &Rgba32FImage| image.pixels().map(|pixel| pixel.0[0] as f64).sum::<f64>();
+		assert!((sum(&image) - sum(&original)).abs() / (width * height) as f64 <= 0.1 / 255.0);
+	}
+}
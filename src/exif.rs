@@ -0,0 +1,218 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use image::DynamicImage;
+
+const TAG_ORIENTATION: u16 = 0x0112;
+
+// The EXIF data is in an APP1 segment, which is limited to 64KB and comes before the image data
+const MAX_HEADER_SIZE: u64 = 128 * 1024;
+
+/**
+ * Read the EXIF orientation tag (1-8) of a JPEG file, if present. Other file formats are not supported.
+ * More info: https://www.media.mit.edu/pia/Research/deepview/exif.html
+ */
+pub fn read_orientation(path: &Path) -> Option<u16> {
+	let mut data = Vec::new();
+	File::open(path).ok()?.take(MAX_HEADER_SIZE).read_to_end(&mut data).ok()?;
+	read_jpeg_orientation(&data)
+}
+
+/**
+ * Read the EXIF orientation tag from the start of a JPEG file
+ */
+fn read_jpeg_orientation(data: &[u8]) -> Option<u16> {
+	if data.get(0..2)? != [0xFF, 0xD8] {
+		// Not a JPEG file
+		return None;
+	}
+
+	// Walk the JPEG segments until the EXIF one
+	let mut position = 2;
+	loop {
+		let marker = *data.get(position + 1)?;
+		if data[position] != 0xFF || marker == 0xD9 || marker == 0xDA {
+			// Invalid segment, end of image, or start of the image data
+			return None;
+		}
+		let length = u16::from_be_bytes(data.get(position + 2..position + 4)?.try_into().ok()?) as usize;
+		let segment = data.get(position + 4..position + 2 + length)?;
+		if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+			return read_tiff_orientation(&segment[6..]);
+		}
+		position += 2 + length;
+	}
+}
+
+/**
+ * Read the orientation tag from the first IFD of EXIF data, which uses the TIFF structure
+ */
+fn read_tiff_orientation(tiff: &[u8]) -> Option<u16> {
+	let is_little_endian = match tiff.get(0..2)? {
+		b"II" => true,
+		b"MM" => false,
+		_ => return None,
+	};
+	let read_u16 = |offset: usize| -> Option<u16> {
+		let bytes = tiff.get(offset..offset + 2)?.try_into().ok()?;
+		Some(if is_little_endian {
+			u16::from_le_bytes(bytes)
+		} else {
+			u16::from_be_bytes(bytes)
+		})
+	};
+	let read_u32 = |offset: usize| -> Option<u32> {
+		let bytes = tiff.get(offset..offset + 4)?.try_into().ok()?;
+		Some(if is_little_endian {
+			u32::from_le_bytes(bytes)
+		} else {
+			u32::from_be_bytes(bytes)
+		})
+	};
+
+	if read_u16(2)? != 42 {
+		return None;
+	}
+	let ifd_offset = read_u32(4)? as usize;
+	let num_entries = read_u16(ifd_offset)? as usize;
+	(0..num_entries)
+		.map(|index| ifd_offset + 2 + index * 12)
+		.find(|&entry| read_u16(entry) == Some(TAG_ORIENTATION))
+		.and_then(|entry| read_u16(entry + 8))
+		.filter(|orientation| (1..=8).contains(orientation))
+}
+
+/**
+ * Rotate and/or flip an image so it's shown upright, according to its EXIF orientation tag
+ */
+pub fn apply_orientation(image: DynamicImage, orientation: u16) -> DynamicImage {
+	match orientation {
+		2 => image.fliph(),
+		3 => image.rotate180(),
+		4 => image.flipv(),
+		5 => image.rotate90().fliph(),
+		6 => image.rotate90(),
+		7 => image.rotate270().fliph(),
+		8 => image.rotate270(),
+		_ => image,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{GrayImage, Luma};
+
+	/// EXIF data with a single entry in its first IFD
+	fn tiff(is_little_endian: bool, tag: u16, value: u16) -> Vec<u8> {
+		let u16_bytes = |value: u16| {
+			if is_little_endian {
+				value.to_le_bytes()
+			} else {
+				value.to_be_bytes()
+			}
+		};
+		let u32_bytes = |value: u32| {
+			if is_little_endian {
+				value.to_le_bytes()
+			} else {
+				value.to_be_bytes()
+			}
+		};
+		let mut data = if is_little_endian {
+			b"II".to_vec()
+		} else {
+			b"MM".to_vec()
+		};
+		data.extend(u16_bytes(42));
+		data.extend(u32_bytes(8));
+		data.extend(u16_bytes(1));
+		// Tag, type (short), count, and value, padded to 4 bytes
+		data.extend(u16_bytes(tag));
+		data.extend(u16_bytes(3));
+		data.extend(u32_bytes(1));
+		data.extend(u16_bytes(value));
+		data.extend([0, 0]);
+		data
+	}
+
+	/// The start of a JPEG file, with another segment before the EXIF one
+	fn jpeg(tiff: &[u8]) -> Vec<u8> {
+		let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xE1];
+		data.extend((2 + 6 + tiff.len() as u16).to_be_bytes());
+		data.extend(b"Exif\0\0");
+		data.extend(tiff);
+		data.extend([0xFF, 0xDA]);
+		data
+	}
+
+	#[test]
+	fn reads_orientation_in_both_byte_orders() {
+		for orientation in 1..=8 {
+			assert_eq!(
+				read_jpeg_orientation(&jpeg(&tiff(true, TAG_ORIENTATION, orientation))),
+				Some(orientation)
+			);
+			assert_eq!(
+				read_jpeg_orientation(&jpeg(&tiff(false, TAG_ORIENTATION, orientation))),
+				Some(orientation)
+			);
+		}
+		// Other tags, and values out of range, are ignored
+		assert_eq!(read_jpeg_orientation(&jpeg(&tiff(true, 0x0110, 6))), None);
+		assert_eq!(read_jpeg_orientation(&jpeg(&tiff(false, TAG_ORIENTATION, 9))), None);
+	}
+
+	#[test]
+	fn invalid_data_has_no_orientation() {
+		let valid = jpeg(&tiff(true, TAG_ORIENTATION, 6));
+		// Truncated anywhere
+		for length in 0..valid.len() - 2 {
+			assert_eq!(read_jpeg_orientation(&valid[..length]), None, "{length} bytes");
+		}
+		// Corrupt in different places: not a JPEG, a broken segment marker or length, an unknown byte order, a
+		// wrong TIFF header, or an IFD out of bounds
+		for (position, value) in
+			[(1, 0xD9), (2, 0x00), (5, 0x00), (5, 0xFF), (18, b'X'), (20, 43), (22, 0xF0)]
+		{
+			let mut corrupt = valid.clone();
+			corrupt[position] = value;
+			assert_eq!(read_jpeg_orientation(&corrupt), None, "{value} at {position}");
+		}
+	}
+
+	#[test]
+	fn applies_orientations() {
+		let (width, height) = (3, 2);
+		let image = GrayImage::from_fn(width, height, |x, y| Luma([(y * width + x) as u8]));
+		for orientation in 1..=8 {
+			// Where each pixel of the result comes from in the original image, as described by the EXIF standard
+			let source = |x: u32, y: u32| match orientation {
+				2 => (2 - x, y),
+				3 => (2 - x, 1 - y),
+				4 => (x, 1 - y),
+				5 => (y, x),
+				6 => (y, 1 - x),
+				7 => (2 - y, 1 - x),
+				8 => (2 - y, x),
+				_ => (x, y),
+			};
+			let result = apply_orientation(DynamicImage::ImageLuma8(image.clone()), orientation).into_luma8();
+			let expected_size = if orientation >= 5 {
+				(height, width)
+			} else {
+				(width, height)
+			};
+			assert_eq!(result.dimensions(), expected_size, "orientation {orientation}");
+			for (x, y, pixel) in result.enumerate_pixels() {
+				let (source_x, source_y) = source(x, y);
+				assert_eq!(
+					pixel,
+					image.get_pixel(source_x, source_y),
+					"orientation {orientation} at ({x}, {y})"
+				);
+			}
+		}
+	}
+}
@@ -0,0 +1,275 @@
+use rust_faces::{Face, Provider, Rect};
+use strum_macros::{Display, EnumString};
+
+use crate::geom::{WHf, XYf, distance};
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum Detector {
+	#[strum(serialize = "blazeface")]
+	BlazeFace,
+	#[strum(serialize = "mtcnn")]
+	MtCnn,
+}
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum InferenceProvider {
+	#[strum(serialize = "cpu")]
+	Cpu,
+	#[strum(serialize = "cuda")]
+	Cuda,
+	#[strum(serialize = "coreml")]
+	CoreMl,
+	#[strum(serialize = "directml")]
+	DirectMl,
+}
+
+impl InferenceProvider {
+	/// The ONNX Runtime provider to run inference with; GPU providers use the first device. rust-faces doesn't
+	/// have a DirectML provider, so there's none for it
+	pub fn to_provider(&self) -> Option<Provider> {
+		match self {
+			Self::Cpu => Some(Provider::OrtCpu),
+			Self::Cuda => Some(Provider::OrtCuda(0)),
+			Self::CoreMl => Some(Provider::OrtCoreMl),
+			Self::DirectMl => None,
+		}
+	}
+}
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum AlignBy {
+	#[strum(serialize = "bbox")]
+	BoundingBox,
+	#[strum(serialize = "eyes")]
+	Eyes,
+}
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum CenterOn {
+	#[strum(serialize = "bbox")]
+	BoundingBox,
+	#[strum(serialize = "eyes")]
+	Eyes,
+	#[strum(serialize = "nose")]
+	Nose,
+}
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum FaceSelection {
+	#[strum(serialize = "single")]
+	Single,
+	#[strum(serialize = "largest")]
+	Largest,
+	#[strum(serialize = "most-confident")]
+	MostConfident,
+	#[strum(serialize = "center")]
+	Center,
+	#[strum(serialize = "all")]
+	All,
+}
+
+/// How to rank faces when only some of them can be kept
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum FaceRanking {
+	#[strum(serialize = "confidence")]
+	Confidence,
+	#[strum(serialize = "size")]
+	Size,
+}
+
+#[inline(always)]
+fn face_area(face: &Face) -> f32 {
+	face.rect.width * face.rect.height
+}
+
+#[inline(always)]
+fn face_center(face: &Face) -> XYf {
+	(face.rect.x + face.rect.width / 2.0, face.rect.y + face.rect.height / 2.0)
+}
+
+/// Checks whether a face's rectangle is at least a margin (horizontal, vertical) away from all image edges
+pub fn is_away_from_edges(face: &Face, image_size: WHf, margin: XYf) -> bool {
+	face.rect.x >= margin.0
+		&& face.rect.y >= margin.1
+		&& face.rect.x + face.rect.width <= image_size.0 - margin.0
+		&& face.rect.y + face.rect.height <= image_size.1 - margin.1
+}
+
+/// Checks whether a face's rectangle is at least a size (width, height) in both dimensions
+pub fn is_at_least_size(face: &Face, size: WHf) -> bool {
+	face.rect.width >= size.0 && face.rect.height >= size.1
+}
+
+/// Checks whether a face's rectangle is at most a size (width, height) in both dimensions
+pub fn is_at_most_size(face: &Face, size: WHf) -> bool {
+	face.rect.width <= size.0 && face.rect.height <= size.1
+}
+
+/// Scales a face's rectangle and landmarks, e.g. to go from a downscaled image back to the original one.
+pub fn scale_face(face: Face, scale: f32) -> Face {
+	Face {
+		rect: Rect {
+			x: face.rect.x * scale,
+			y: face.rect.y * scale,
+			width: face.rect.width * scale,
+			height: face.rect.height * scale,
+		},
+		landmarks: face
+			.landmarks
+			.map(|landmarks| landmarks.into_iter().map(|(x, y)| (x * scale, y * scale)).collect()),
+		..face
+	}
+}
+
+/// Finds the position of both eyes (left and right, as seen in the image) from a face's landmarks, if
+/// the detector provided them. Both BlazeFace and MTCNN report the eyes as their first two landmarks.
+pub fn eye_positions(face: &Face) -> Option<(XYf, XYf)> {
+	match &face.landmarks {
+		Some(landmarks) if landmarks.len() >= 2 => {
+			let (eye1, eye2) = (landmarks[0], landmarks[1]);
+			if eye1.0 <= eye2.0 {
+				Some((eye1, eye2))
+			} else {
+				Some((eye2, eye1))
+			}
+		}
+		_ => None,
+	}
+}
+
+/// Finds the position of the tip of the nose from a face's landmarks, if the detector provided them. Both
+/// BlazeFace and MTCNN report it as their third landmark.
+pub fn nose_position(face: &Face) -> Option<XYf> {
+	match &face.landmarks {
+		Some(landmarks) if landmarks.len() >= 3 => Some(landmarks[2]),
+		_ => None,
+	}
+}
+
+/// Picks the faces to be used from all faces detected in an image. This is a single face, or none, unless
+/// using all faces. Ties always go to the face detected first, so results are reproducible.
+pub fn select_faces<'a>(faces: &'a [Face], selection: &FaceSelection, image_size: WHf) -> Vec<&'a Face> {
+	match selection {
+		FaceSelection::All => faces.iter().collect(),
+		_ => select_face(faces, selection, image_size).into_iter().collect(),
+	}
+}
+
+/// Keeps at most a number of faces, the best ones first according to the ranking. Ties keep the order in which
+/// faces were detected, so results are reproducible.
+pub fn keep_top_faces(faces: &mut Vec<&Face>, max_faces: usize, ranking: &FaceRanking) {
+	match ranking {
+		FaceRanking::Confidence => faces.sort_by(|a, b| b.confidence.total_cmp(&a.confidence)),
+		FaceRanking::Size => faces.sort_by(|a, b| face_area(b).total_cmp(&face_area(a))),
+	}
+	faces.truncate(max_faces);
+}
+
+fn select_face<'a>(faces: &'a [Face], selection: &FaceSelection, image_size: WHf) -> Option<&'a Face> {
+	match selection {
+		FaceSelection::Single => {
+			if faces.len() == 1 {
+				Some(&faces[0])
+			} else {
+				None
+			}
+		}
+		FaceSelection::Largest => faces.iter().reduce(|best, face| {
+			if face_area(face) > face_area(best) {
+				face
+			} else {
+				best
+			}
+		}),
+		FaceSelection::MostConfident => faces.iter().reduce(|best, face| {
+			if face.confidence > best.confidence {
+				face
+			} else {
+				best
+			}
+		}),
+		FaceSelection::Center => {
+			let image_center: XYf = (image_size.0 / 2.0, image_size.1 / 2.0);
+			faces.iter().reduce(|best, face| {
+				if distance(face_center(face), image_center) < distance(face_center(best), image_center) {
+					face
+				} else {
+					best
+				}
+			})
+		}
+		// Handled by `select_faces()`
+		FaceSelection::All => faces.first(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn face(x: f32, y: f32, size: f32, confidence: f32) -> Face {
+		Face {
+			rect: Rect {
+				x,
+				y,
+				width: size,
+				height: size,
+			},
+			confidence,
+			landmarks: None,
+		}
+	}
+
+	/// Index of each face picked, to compare them by identity
+	fn indices(faces: &[Face], picked: &[&Face]) -> Vec<usize> {
+		picked
+			.iter()
+			.map(|picked| faces.iter().position(|face| std::ptr::eq(face, *picked)).unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn selects_faces() {
+		let image_size = (100.0, 100.0);
+		let faces = [face(0.0, 0.0, 20.0, 0.8), face(40.0, 40.0, 20.0, 0.7), face(60.0, 0.0, 30.0, 0.9)];
+		let select = |faces: &[Face], selection| indices(faces, &select_faces(faces, &selection, image_size));
+		assert_eq!(select(&faces, FaceSelection::Largest), [2]);
+		assert_eq!(select(&faces, FaceSelection::MostConfident), [2]);
+		assert_eq!(select(&faces, FaceSelection::Center), [1]);
+		assert_eq!(select(&faces, FaceSelection::All), [0, 1, 2]);
+		// A single face is only used if it's the only one
+		assert_eq!(select(&faces, FaceSelection::Single), Vec::<usize>::new());
+		assert_eq!(select(&faces[1..2], FaceSelection::Single), [0]);
+		assert_eq!(select(&[], FaceSelection::Largest), Vec::<usize>::new());
+	}
+
+	#[test]
+	fn selection_ties_go_to_the_first_face() {
+		let image_size = (100.0, 100.0);
+		// Same size and confidence, and both as far from the center
+		let faces = [face(10.0, 40.0, 20.0, 0.9), face(70.0, 40.0, 20.0, 0.9)];
+		for selection in [FaceSelection::Largest, FaceSelection::MostConfident, FaceSelection::Center] {
+			assert_eq!(indices(&faces, &select_faces(&faces, &selection, image_size)), [0], "{selection}");
+		}
+	}
+
+	#[test]
+	fn keeps_top_faces() {
+		let faces = [
+			face(0.0, 0.0, 10.0, 0.9),
+			face(0.0, 0.0, 30.0, 0.7),
+			face(0.0, 0.0, 20.0, 0.9),
+			face(0.0, 0.0, 30.0, 0.8),
+		];
+		let keep = |max_faces, ranking| {
+			let mut picked = faces.iter().collect();
+			keep_top_faces(&mut picked, max_faces, &ranking);
+			indices(&faces, &picked)
+		};
+		// Ties keep the order in which faces were detected
+		assert_eq!(keep(3, FaceRanking::Confidence), [0, 2, 3]);
+		assert_eq!(keep(2, FaceRanking::Size), [1, 3]);
+		assert_eq!(keep(10, FaceRanking::Size), [1, 3, 2, 0]);
+		assert_eq!(keep(0, FaceRanking::Confidence), Vec::<usize>::new());
+	}
+}
@@ -0,0 +1,153 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use glob::GlobError;
+use strum_macros::{Display, EnumString};
+
+use crate::geom::WHi;
+use crate::random::shuffle;
+use crate::rng::Rng;
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum SortOrder {
+	#[strum(serialize = "name")]
+	Name,
+	#[strum(serialize = "name-desc")]
+	NameDescending,
+	#[strum(serialize = "none")]
+	None,
+	#[strum(serialize = "random")]
+	Random,
+}
+
+/// Gets the path of a file found with a glob pattern, even if it can't be read.
+fn file_path<T: AsRef<Path>>(file: &Result<T, GlobError>) -> &Path {
+	match file {
+		Ok(path) => path.as_ref(),
+		Err(err) => err.path(),
+	}
+}
+
+/// Sorts the files found with glob patterns, whose order otherwise depends on the file system. Random
+/// sorting depends only on the random number generator, so it can be reproduced.
+pub fn sort_files<T: AsRef<Path>>(files: &mut [Result<T, GlobError>], order: &SortOrder, rng: &mut Rng) {
+	match order {
+		SortOrder::Name => files.sort_by(|a, b| file_path(a).cmp(file_path(b))),
+		SortOrder::NameDescending => files.sort_by(|a, b| file_path(b).cmp(file_path(a))),
+		SortOrder::None => (),
+		SortOrder::Random => {
+			files.sort_by(|a, b| file_path(a).cmp(file_path(b)));
+			shuffle(rng, files);
+		}
+	}
+}
+
+/// Picks a number of files at random, without replacement, keeping the order they were in. Like sorting, this
+/// depends only on the random number generator, so it can be reproduced.
+pub fn sample_files<T>(files: Vec<T>, count: usize, rng: &mut Rng) -> Vec<T> {
+	if count >= files.len() {
+		return files;
+	}
+	let mut indices: Vec<usize> = (0..files.len()).collect();
+	shuffle(rng, &mut indices);
+	let mut is_picked = vec![false; files.len()];
+	for &index in &indices[..count] {
+		is_picked[index] = true;
+	}
+	files.into_iter().zip(is_picked).filter(|(_, is_picked)| *is_picked).map(|(file, _)| file).collect()
+}
+
+/// Reads a list of files, one path per line, from a text file, or from the standard input if the path is "-".
+/// Blank lines, and lines starting with "#", are skipped.
+pub fn read_file_list(path: &Path) -> io::Result<Vec<PathBuf>> {
+	let contents = if path == Path::new("-") {
+		let mut contents = String::new();
+		io::stdin().read_to_string(&mut contents)?;
+		contents
+	} else {
+		fs::read_to_string(path)?
+	};
+	Ok(contents
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(PathBuf::from)
+		.collect())
+}
+
+/// Gets the path of an output file for an image size, replacing "{w}" and "{h}" in its file name with the
+/// width and height (e.g. "out_{w}x{h}.png"). When there are many sizes and the name uses neither, the size is
+/// added before the extension, so files don't overwrite each other.
+pub fn output_path_for_size(path: &Path, size: WHi, has_many_sizes: bool) -> PathBuf {
+	let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+	let new_file_name = if file_name.contains("{w}") || file_name.contains("{h}") {
+		file_name.replace("{w}", &size.0.to_string()).replace("{h}", &size.1.to_string())
+	} else if has_many_sizes {
+		let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+		match path.extension() {
+			Some(extension) => format!("{}_{}x{}.{}", stem, size.0, size.1, extension.to_string_lossy()),
+			None => format!("{}_{}x{}", stem, size.0, size.1),
+		}
+	} else {
+		return path.to_path_buf();
+	};
+	path.with_file_name(new_file_name)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn random_order_only_depends_on_the_seed() {
+		let names = ["c.jpg", "a.jpg", "e.jpg", "b.jpg", "d.jpg", "f.jpg"];
+		let shuffled = |names: &[&str], seed| {
+			let mut files = names.iter().map(|name| Ok(PathBuf::from(name))).collect::<Vec<_>>();
+			sort_files(&mut files, &SortOrder::Random, &mut Rng::from_seed(seed));
+			files.into_iter().map(Result::unwrap).collect::<Vec<PathBuf>>()
+		};
+		// The same files in any order get the same order for a seed
+		let mut reversed = names;
+		reversed.reverse();
+		assert_eq!(shuffled(&names, 1), shuffled(&reversed, 1));
+		assert_ne!(shuffled(&names, 1), shuffled(&names, 2));
+		let mut sorted = shuffled(&names, 1);
+		sorted.sort();
+		assert_eq!(sorted, ["a.jpg", "b.jpg", "c.jpg", "d.jpg", "e.jpg", "f.jpg"].map(PathBuf::from));
+	}
+
+	#[test]
+	fn samples_files() {
+		let files = (0..20).collect::<Vec<u32>>();
+		let sample = sample_files(files.clone(), 5, &mut Rng::from_seed(1));
+		assert_eq!(sample.len(), 5);
+		// In their original order, without repeating any
+		assert!(sample.windows(2).all(|pair| pair[0] < pair[1]));
+		assert_eq!(sample, sample_files(files.clone(), 5, &mut Rng::from_seed(1)));
+		// Asking for more files than there are uses all of them
+		assert_eq!(sample_files(files.clone(), 20, &mut Rng::from_seed(1)), files);
+		assert_eq!(sample_files(files.clone(), 100, &mut Rng::from_seed(1)), files);
+	}
+
+	#[test]
+	fn reads_file_lists() {
+		let path = std::env::temp_dir().join(format!("face-stack-file-list-{}.txt", std::process::id()));
+		fs::write(&path, "# Faces\nimages/a.jpg\n\n  images/b c.jpg  \r\n#images/c.jpg\n").unwrap();
+		let files = read_file_list(&path);
+		fs::remove_file(&path).unwrap();
+		assert_eq!(files.unwrap(), ["images/a.jpg", "images/b c.jpg"].map(PathBuf::from));
+		assert!(read_file_list(Path::new("does-not-exist.txt")).is_err());
+	}
+
+	#[test]
+	fn output_paths_for_sizes() {
+		let path_for =
+			|path: &str, has_many_sizes| output_path_for_size(Path::new(path), (640, 480), has_many_sizes);
+		assert_eq!(path_for("out/stack.png", false), Path::new("out/stack.png"));
+		assert_eq!(path_for("out/stack.png", true), Path::new("out/stack_640x480.png"));
+		assert_eq!(path_for("stack", true), Path::new("stack_640x480"));
+		assert_eq!(path_for("out/stack_{w}x{h}.png", false), Path::new("out/stack_640x480.png"));
+		assert_eq!(path_for("{w}/stack_{h}.png", true), Path::new("{w}/stack_480.png"));
+	}
+}
@@ -8,9 +8,13 @@ pub type XYXYi = (i32, i32, i32, i32);
 pub type XYWHi = (i32, i32, u32, u32);
 
 /**
- * Find the expected scale to fit a rectangle (w, h) inside another.
+ * Find the expected scale to fit a rectangle (w, h) inside another. If either rectangle has no area, the
+ * result has no area either.
  */
 pub fn fit_inside(outside_rect: WHf, inside_rect: WHf) -> WHf {
+	if outside_rect.0 <= 0.0 || outside_rect.1 <= 0.0 || inside_rect.0 <= 0.0 || inside_rect.1 <= 0.0 {
+		return (0.0, 0.0);
+	}
 	let inside_ar = inside_rect.0 / inside_rect.1;
 	let outside_ar = outside_rect.0 / outside_rect.1;
 	if inside_ar > outside_ar {
@@ -23,19 +27,37 @@ pub fn fit_inside(outside_rect: WHf, inside_rect: WHf) -> WHf {
 }
 
 /**
- * Find the intersection rectangle between two rectangles
+ * Find the intersection rectangle between two rectangles. Rectangles that only touch, or have no area, don't
+ * intersect
  */
 pub fn intersect(rect1: XYWHi, rect2: XYWHi) -> Option<XYWHi> {
 	let xyxy1: XYXYi = (rect1.0, rect1.1, rect1.0 + rect1.2 as i32, rect1.1 + rect1.3 as i32);
 	let xyxy2: XYXYi = (rect2.0, rect2.1, rect2.0 + rect2.2 as i32, rect2.1 + rect2.3 as i32);
 	let xyxyi = (xyxy1.0.max(xyxy2.0), xyxy1.1.max(xyxy2.1), xyxy1.2.min(xyxy2.2), xyxy1.3.min(xyxy2.3));
-	if xyxyi.0 > xyxyi.2 || xyxyi.1 > xyxyi.3 {
+	if xyxyi.0 >= xyxyi.2 || xyxyi.1 >= xyxyi.3 {
 		None
 	} else {
 		Some((xyxyi.0, xyxyi.1, (xyxyi.2 - xyxyi.0) as u32, (xyxyi.3 - xyxyi.1) as u32))
 	}
 }
 
+/**
+ * Find the Euclidean distance between two points
+ */
+pub fn distance(xy1: XYf, xy2: XYf) -> f32 {
+	((xy2.0 - xy1.0).powi(2) + (xy2.1 - xy1.1).powi(2)).sqrt()
+}
+
+/**
+ * Rotate a point around a pivot, by an angle in radians (clockwise, since y points down)
+ */
+pub fn rotate_point(xy: XYf, pivot: XYf, angle: f32) -> XYf {
+	let (sin, cos) = angle.sin_cos();
+	let dx = xy.0 - pivot.0;
+	let dy = xy.1 - pivot.1;
+	(pivot.0 + dx * cos - dy * sin, pivot.1 + dx * sin + dy * cos)
+}
+
 pub fn xyf_to_xyi(xy: XYf) -> XYi {
 	(xy.0.round() as i32, xy.1.round() as i32)
 }
@@ -55,3 +77,47 @@ pub fn xywhi_to_xywhf(xywh: XYWHi) -> XYWHf {
 pub fn xywhf_to_xywhi(xywh: XYWHf) -> XYWHi {
 	(xywh.0.round() as i32, xywh.1.round() as i32, xywh.2.round() as u32, xywh.3.round() as u32)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fit_inside_keeps_aspect_ratio() {
+		assert_eq!(fit_inside((200.0, 100.0), (50.0, 50.0)), (100.0, 100.0));
+		assert_eq!(fit_inside((200.0, 100.0), (400.0, 100.0)), (200.0, 50.0));
+		assert_eq!(fit_inside((100.0, 200.0), (75.0, 100.0)), (100.0, 100.0 / 0.75));
+	}
+
+	#[test]
+	fn fit_inside_empty() {
+		assert_eq!(fit_inside((200.0, 100.0), (0.0, 50.0)), (0.0, 0.0));
+		assert_eq!(fit_inside((200.0, 100.0), (50.0, 0.0)), (0.0, 0.0));
+		assert_eq!(fit_inside((0.0, 0.0), (50.0, 50.0)), (0.0, 0.0));
+		assert_eq!(fit_inside((200.0, 0.0), (50.0, 50.0)), (0.0, 0.0));
+		assert_eq!(fit_inside((0.0, 0.0), (0.0, 0.0)), (0.0, 0.0));
+	}
+
+	#[test]
+	fn intersect_overlapping() {
+		assert_eq!(intersect((0, 0, 10, 10), (5, 5, 10, 10)), Some((5, 5, 5, 5)));
+		assert_eq!(intersect((0, 0, 10, 10), (2, 3, 4, 5)), Some((2, 3, 4, 5)));
+		assert_eq!(intersect((-5, -5, 10, 10), (0, 0, 10, 10)), Some((0, 0, 5, 5)));
+		assert_eq!(intersect((5, 5, 10, 10), (0, 0, 10, 10)), intersect((0, 0, 10, 10), (5, 5, 10, 10)));
+	}
+
+	#[test]
+	fn intersect_touching() {
+		assert_eq!(intersect((0, 0, 10, 10), (10, 0, 10, 10)), None);
+		assert_eq!(intersect((0, 0, 10, 10), (0, 10, 10, 10)), None);
+		assert_eq!(intersect((0, 0, 10, 10), (-10, -10, 10, 10)), None);
+	}
+
+	#[test]
+	fn intersect_disjoint() {
+		assert_eq!(intersect((0, 0, 10, 10), (20, 20, 10, 10)), None);
+		assert_eq!(intersect((0, 0, 10, 10), (-20, 0, 10, 10)), None);
+		// Rectangles without an area don't intersect anything
+		assert_eq!(intersect((0, 0, 10, 10), (5, 5, 0, 0)), None);
+	}
+}
@@ -0,0 +1,82 @@
+use image::{RgbImage, imageops};
+
+use crate::blending::{Luma, luma, pixel_u8_to_f32};
+
+// Width and height of the grayscale image hashed; 8x8 gives one bit for each pixel of a 64-bit hash
+const HASH_SIZE: u32 = 8;
+
+/**
+ * Get a perceptual hash of an image (an "average hash"): it's shrunk to 8x8 pixels in grayscale, and each bit
+ * tells whether a pixel is brighter than the average. Similar images get hashes with few different bits, even
+ * after resizing or recompressing
+ */
+pub fn average_hash(image: &RgbImage, weights: &Luma) -> u64 {
+	let thumbnail = imageops::thumbnail(image, HASH_SIZE, HASH_SIZE);
+	let values: Vec<f32> = thumbnail.pixels().map(|pixel| luma(pixel_u8_to_f32(&pixel.0), weights)).collect();
+	let average = values.iter().sum::<f32>() / values.len() as f32;
+	values.iter().enumerate().fold(0, |hash, (index, value)| {
+		if *value > average {
+			hash | (1 << index)
+		} else {
+			hash
+		}
+	})
+}
+
+/// Number of bits that are different between two hashes.
+#[inline(always)]
+pub fn hamming_distance(hash1: u64, hash2: u64) -> u32 {
+	(hash1 ^ hash2).count_ones()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::Rgb;
+
+	// The default `--dedupe-distance`
+	const DISTANCE: u32 = 4;
+
+	/// An image with a bright blob over a gradient, like a face in front of a background
+	fn image(width: u32, height: u32, blob_center: (f32, f32)) -> RgbImage {
+		RgbImage::from_fn(width, height, |x, y| {
+			let (u, v) = (x as f32 / width as f32, y as f32 / height as f32);
+			let blob = (1.0 - ((u - blob_center.0).powi(2) + (v - blob_center.1).powi(2)) * 8.0).max(0.0);
+			let value = (u * 0.5 + blob * 0.5) * 255.0;
+			Rgb([value as u8, (value * 0.8) as u8, (value * 0.6) as u8])
+		})
+	}
+
+	#[test]
+	fn similar_images_have_similar_hashes() {
+		let original = image(240, 320, (0.3, 0.4));
+		let hash = average_hash(&original, &Luma::Rec709);
+		assert_eq!(hash, average_hash(&original.clone(), &Luma::Rec709));
+		// Resized, like the same photo saved at another size
+		for (width, height) in [(120, 160), (300, 400), (237, 322)] {
+			let resized = imageops::resize(&original, width, height, imageops::Triangle);
+			let distance = hamming_distance(hash, average_hash(&resized, &Luma::Rec709));
+			assert!(distance <= DISTANCE, "{distance} bits at {width}x{height}");
+		}
+	}
+
+	#[test]
+	fn different_images_have_different_hashes() {
+		let hash = average_hash(&image(240, 320, (0.3, 0.4)), &Luma::Rec709);
+		for other in [
+			image(240, 320, (0.7, 0.6)),
+			image(240, 320, (0.5, 0.9)),
+			imageops::flip_horizontal(&image(240, 320, (0.3, 0.4))),
+		] {
+			let distance = hamming_distance(hash, average_hash(&other, &Luma::Rec709));
+			assert!(distance > DISTANCE, "{distance} bits");
+		}
+	}
+
+	#[test]
+	fn distance_counts_different_bits() {
+		assert_eq!(hamming_distance(0, 0), 0);
+		assert_eq!(hamming_distance(0b1011, 0b0010), 2);
+		assert_eq!(hamming_distance(0, u64::MAX), 64);
+	}
+}
@@ -0,0 +1,27 @@
+pub mod adjustments;
+pub mod animation;
+pub mod blending;
+#[cfg(feature = "simd")]
+pub mod blending_simd;
+pub mod cache;
+pub mod compositing;
+pub mod config;
+pub mod contact_sheet;
+pub mod dithering;
+pub mod exif;
+pub mod faces;
+pub mod files;
+pub mod geom;
+pub mod hashing;
+pub mod logging;
+pub mod masking;
+pub mod metadata;
+pub mod parsing;
+pub mod progress;
+pub mod random;
+pub mod rng;
+pub mod stacking;
+pub mod terminal;
+pub mod timings;
+pub mod transform;
+pub mod units;
@@ -0,0 +1,10 @@
+/// How much is printed while running. Levels are ordered, so each one prints everything the previous ones do.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum LogLevel {
+	/// Only errors, warnings, and the final summary
+	Quiet,
+	/// Also the initial settings, and a progress bar
+	Normal,
+	/// Also the details of each file and face being processed, instead of the progress bar
+	Verbose,
+}
@@ -1,292 +1,1826 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use glob::{GlobError, glob};
-use image::{ImageBuffer, Pixel, Rgb, Rgb32FImage, RgbImage, imageops};
-use rng::Rng;
+use glob::{GlobError, MatchOptions, glob_with};
+use image::codecs::hdr::HdrEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::{
+	DynamicImage, ImageBuffer, ImageError, ImageFormat, Pixel, Rgb, Rgba, Rgba32FImage, RgbaImage, imageops,
+};
+use rayon::prelude::*;
 use rust_faces::{
-	BlazeFaceParams, FaceDetection, FaceDetectorBuilder, InferParams, Provider, ToArray3, ToRgb8,
+	BlazeFaceParams, Face, FaceDetection, FaceDetector, FaceDetectorBuilder, InferParams, MtCnnParams, Nms,
+	Provider, ToArray3,
 };
 use structopt::StructOpt;
+use strum::IntoEnumIterator;
+use webp::Encoder;
 
-use blending::{BlendingMode, blend_pixel, pixel_u8_to_f32};
-use geom::{WHf, WHi, XYWHi, XYi, fit_inside, intersect, whf_to_whi, xyf_to_xyi};
-use parsing::{
-	parse_image_dimensions, parse_weighted_blending_mode, parse_weighted_float_pair, parse_weighted_size_pair,
+use face_stack::adjustments::{WhiteBalance, balance_gray_world, desaturate, normalize_levels, tint};
+use face_stack::animation::{Animation, FrameStrategy, read_frames};
+use face_stack::blending::{BlendOrder, BlendingMode, Luma, OpacityDecay, linear_to_srgb, srgb_to_linear};
+use face_stack::cache::{load_faces, save_faces};
+use face_stack::compositing::{blend_image, stack_image};
+use face_stack::config::Config;
+use face_stack::contact_sheet::ContactSheet;
+use face_stack::dithering::dither_floyd_steinberg;
+use face_stack::exif::{apply_orientation, read_orientation};
+use face_stack::faces::{
+	AlignBy, CenterOn, Detector, FaceRanking, FaceSelection, InferenceProvider, eye_positions,
+	is_at_least_size, is_at_most_size, is_away_from_edges, keep_top_faces, nose_position, scale_face,
+	select_faces,
+};
+use face_stack::files::{SortOrder, output_path_for_size, read_file_list, sample_files, sort_files};
+use face_stack::geom::{
+	WHf, WHi, XYWHf, XYWHi, XYf, XYi, distance, fit_inside, intersect, rotate_point, whf_to_whi, xyf_to_xyi,
+};
+use face_stack::hashing::{average_hash, hamming_distance};
+use face_stack::logging::LogLevel;
+use face_stack::masking::{CropAnchor, Mask, MaskShape, Vignette};
+use face_stack::metadata::{LayerMetadata, Metadata, save_metadata};
+use face_stack::parsing::{
+	parse_amount, parse_anchor, parse_background, parse_bit_depth, parse_downscale, parse_face_size,
+	parse_image_dimensions, parse_image_format, parse_opacity_decay, parse_percentage,
+	parse_positive_integer, parse_quality, parse_size, parse_threshold, parse_vignette,
+	parse_weighted_blending_modes, parse_weighted_float_pair, parse_weighted_float_pair_triple,
+	parse_weighted_signed_float_pair, parse_weighted_size_pair, parse_weighted_tint,
 };
-use random::{get_random_entry_weighted, get_random_range_weighted, get_random_size_range_weighted};
-use units::{SizeUnit, WeightedValue};
+use face_stack::progress::ProgressBar;
+use face_stack::random::{
+	Deck, get_random_entry_weighted, get_random_range_triple_weighted, get_random_range_weighted,
+	get_random_size_range_weighted, get_rng_for_frame, get_rng_for_path,
+};
+use face_stack::rng::Rng;
+use face_stack::stacking::{Stack, StackMode};
+use face_stack::terminal;
+use face_stack::timings::{Stage, Timings};
+use face_stack::transform::{ImageFit, fit_image, flatten_alpha, resize_to_cover, rotate_image};
+use face_stack::units::{SizeUnit, WeightedValue};
+
+/**
+ * Rotate and scale an image so its face matches the target face box, with its scale multiplied by `scale`
+ * (from the face scale and scale jitter). Returns the aligned image, the point in it that should be placed at
+ * the anchor of the output image, and the size of the face in it
+ */
+fn align_face(
+	image: &RgbaImage,
+	face: &Face,
+	opt: &Opt,
+	target_faces_rect: WHf,
+	target_eye_distance: f32,
+	scale: f32,
+	log: &mut String,
+) -> (RgbaImage, XYf, WHf) {
+	let mut rotated_image: Option<RgbaImage> = None;
+	let mut face_rect: XYWHf = (face.rect.x, face.rect.y, face.rect.width, face.rect.height);
+
+	// Rotate the image around the face so the eyes are level, if we know where they are
+	let mut eyes = eye_positions(face);
+	let mut nose = nose_position(face);
+	if opt.align_rotation {
+		if let Some((left_eye, right_eye)) = eyes {
+			let angle = (right_eye.1 - left_eye.1).atan2(right_eye.0 - left_eye.0);
+			let face_center: XYf = (face_rect.0 + face_rect.2 / 2.0, face_rect.1 + face_rect.3 / 2.0);
+			let (new_image, rotated_face_center) = rotate_image(image, -angle, face_center);
+			let rotate_landmark = |xy: XYf| {
+				let rotated = rotate_point(xy, face_center, -angle);
+				(
+					rotated.0 - face_center.0 + rotated_face_center.0,
+					rotated.1 - face_center.1 + rotated_face_center.1,
+				)
+			};
+			rotated_image = Some(new_image);
+			face_rect.0 = rotated_face_center.0 - face_rect.2 / 2.0;
+			face_rect.1 = rotated_face_center.1 - face_rect.3 / 2.0;
+			eyes = Some((rotate_landmark(left_eye), rotate_landmark(right_eye)));
+			nose = nose.map(rotate_landmark);
+			log.push_str(&format!(", rotated {:.1}°", -angle.to_degrees()));
+		} else {
+			log.push_str(", no landmarks to align rotation");
+		}
+	}
+	let image = rotated_image.as_ref().unwrap_or(image);
+
+	// Find out how much to scale the image
+	let face_center: XYf = (face_rect.0 + face_rect.2 / 2.0, face_rect.1 + face_rect.3 / 2.0);
+	let (new_image_scale, align_center): (f32, CenterOn) = match (&opt.align_by, eyes) {
+		(AlignBy::Eyes, Some((left_eye, right_eye))) if distance(left_eye, right_eye) > 0.0 => {
+			(target_eye_distance / distance(left_eye, right_eye), CenterOn::Eyes)
+		}
+		_ => {
+			if opt.align_by == AlignBy::Eyes {
+				log.push_str(", no landmarks to align by eyes");
+			}
+
+			// Find out what the face size should be inside our face target box
+			let target_face_rect: WHf = fit_inside(target_faces_rect, (face_rect.2, face_rect.3));
+			(target_face_rect.0 / face_rect.2, CenterOn::BoundingBox)
+		}
+	};
+
+	// Find out which of its points goes at the anchor of the output; by default, the one used for aligning
+	let face_anchor: XYf = match (opt.center_on.as_ref().unwrap_or(&align_center), eyes, nose) {
+		(CenterOn::Eyes, Some((left_eye, right_eye)), _) => {
+			((left_eye.0 + right_eye.0) / 2.0, (left_eye.1 + right_eye.1) / 2.0)
+		}
+		(CenterOn::Nose, _, Some(nose)) => nose,
+		(center_on, _, _) => {
+			if *center_on != CenterOn::BoundingBox {
+				log.push_str(&format!(", no landmarks to center on {}", center_on));
+			}
+			face_center
+		}
+	};
+
+	// Scale the image appropriately
+	let new_image_scale = new_image_scale * scale;
+	let new_image_size: WHi =
+		whf_to_whi((image.width() as f32 * new_image_scale, image.height() as f32 * new_image_scale));
+	let resized_image = imageops::resize(image, new_image_size.0, new_image_size.1, imageops::Lanczos3);
+	(
+		resized_image,
+		(face_anchor.0 * new_image_scale, face_anchor.1 * new_image_scale),
+		(face_rect.2 * new_image_scale, face_rect.3 * new_image_scale),
+	)
+}
+
+/// A face from an input image, aligned and ready to be blended
+struct AlignedFace {
+	image: RgbaImage,
+	/// Point in the image that goes at the anchor of the output image (its center, by default)
+	anchor: XYf,
+	/// Rectangle of the face in the input image, as detected
+	rect: XYWHf,
+	/// Size of the face in the aligned image, as it's painted on the output image
+	size: WHf,
+	confidence: f32,
+	/// Notes about the face, for its line in the output
+	log: String,
+}
 
-pub mod blending;
-pub mod geom;
-pub mod parsing;
-pub mod random;
-pub mod rng;
-pub mod terminal;
-pub mod units;
+/// An input image (or a frame of an animated one) after reading it and aligning its faces
+struct ProcessedImage {
+	faces: Vec<AlignedFace>,
+	/// Notes about the file, for its line in the output
+	log: String,
+	/// Random number generator for the file (or frame), to be used for the rest of its layers' settings
+	rng: Rng,
+	is_valid: bool,
+	num_faces_detected: usize,
+	/// Faces with enough confidence, the right size, and far enough from the edges
+	num_faces_accepted: usize,
+	/// Faces picked to be used; only different from the number of aligned faces in dry runs
+	num_faces_selected: usize,
+	/// Perceptual hash of the image, when deduplicating
+	hash: Option<u64>,
+	timings: Timings,
+}
+
+impl ProcessedImage {
+	fn new(rng: Rng) -> ProcessedImage {
+		ProcessedImage {
+			faces: Vec::new(),
+			log: String::new(),
+			rng,
+			is_valid: false,
+			num_faces_detected: 0,
+			num_faces_accepted: 0,
+			num_faces_selected: 0,
+			hash: None,
+			timings: Timings::default(),
+		}
+	}
+}
+
+/**
+ * Get the settings that change the faces detected in a file, so cached faces are only used with the same ones
+ */
+fn get_cache_settings(opt: &Opt) -> String {
+	format!(
+		"{} {} {} {} {} {} {}",
+		opt.detector,
+		opt.detector_target_size,
+		opt.detector_min_face_size,
+		opt.ignore_exif,
+		opt.detect_scale,
+		opt.score_threshold,
+		opt.nms_threshold
+	)
+}
 
 /**
- * Copy one image on top of another
+ * Read an image file, detect its faces, and align the ones selected. Each frame used from an animated file is
+ * processed as a separate image. This only depends on the file itself, so multiple files can be processed in
+ * parallel
  */
-fn blend_image(
-	bottom: &mut Rgb32FImage,
-	top: &RgbImage,
-	top_offset: XYi,
-	opacity: f32,
-	blending_mode: &BlendingMode,
-	mask: Option<XYWHi>,
-) {
-	// Find paintable intersection between bottom and top
-	let bottom_rect = (0, 0, bottom.width(), bottom.height());
-	let top_rect = (top_offset.0, top_offset.1, top.width(), top.height());
-	let intersection = intersect(bottom_rect, top_rect);
-	if intersection.is_none() {
-		panic!("Cannot blend image; no intersection between bottom and top image.");
-	}
-	let mut intersection_rect = intersection.unwrap();
-
-	// Applies further intersection if a mask is present
-	if let Some(mask) = mask {
-		let mask_intersection = intersect(intersection.unwrap(), mask);
-		if mask_intersection.is_none() {
-			// panic!("Cannot blend image; no intersection between blended and mask.");
-			return;
-		}
-		intersection_rect = mask_intersection.unwrap();
-	}
-
-	let dst_x1 = intersection_rect.0;
-	let dst_y1 = intersection_rect.1;
-	let dst_x2 = intersection_rect.0 + intersection_rect.2 as i32 - 1;
-	let dst_y2 = intersection_rect.1 + intersection_rect.3 as i32 - 1;
-
-	for dst_y in dst_y1..dst_y2 {
-		let src_y = (dst_y - top_offset.1) as u32;
-		for dst_x in dst_x1..dst_x2 {
-			let src_x = (dst_x - top_offset.0) as u32;
-			let bottom_px: [f32; 3] = bottom
-				.get_pixel(dst_x as u32, dst_y as u32)
-				.channels()
-				.to_owned()
-				.try_into()
-				.expect("converting pixels to array");
-			let top_px: [u8; 3] = top
-				.get_pixel(src_x, src_y)
-				.channels()
-				.to_owned()
-				.try_into()
-				.expect("converting pixels to array");
-			let blended = blend_pixel(&bottom_px, &pixel_u8_to_f32(&top_px), opacity, blending_mode);
-			bottom.put_pixel(dst_x as u32, dst_y as u32, Rgb(blended));
+fn process_image(
+	path: &Path,
+	face_detector: &dyn FaceDetector,
+	opt: &Opt,
+	target_faces_rect: WHf,
+	target_eye_distance: f32,
+	seed: u64,
+) -> Vec<ProcessedImage> {
+	let mut timings = Timings::default();
+	match timings.time(Stage::Decode, || read_frames(path, &opt.frame_strategy)) {
+		Ok(frames) => {
+			// Is a valid image file; photos are often stored sideways, with an EXIF tag saying how to show them
+			let orientation = (!opt.ignore_exif).then(|| read_orientation(path)).flatten();
+			let num_frames = frames.len();
+			let mut processed_images = frames
+				.into_iter()
+				.enumerate()
+				.map(|(frame_index, mut img)| {
+					if let Some(orientation) = orientation {
+						img = timings.time(Stage::Decode, || apply_orientation(img, orientation));
+					}
+					// Frames get their own random values, like different files would
+					let frame = (num_frames > 1).then_some((frame_index, num_frames));
+					let rng = match frame {
+						Some((frame_index, _)) => get_rng_for_frame(seed, path, frame_index),
+						None => get_rng_for_path(seed, path),
+					};
+					process_frame(
+						path,
+						img,
+						frame,
+						rng,
+						face_detector,
+						opt,
+						target_faces_rect,
+						target_eye_distance,
+					)
+				})
+				.collect::<Vec<ProcessedImage>>();
+			// Decoding the file is counted once, with its first frame
+			processed_images[0].timings.add(&timings);
+			processed_images
+		}
+		Err(err) => {
+			// Not an image, or a format or color type that can't be read
+			let mut processed_image = ProcessedImage::new(get_rng_for_path(seed, path));
+			processed_image.log = format!("; invalid image ({}), skipping.", err);
+			processed_image.timings = timings;
+			vec![processed_image]
 		}
 	}
 }
 
+/**
+ * Detect the faces of an image (or of a frame of an animated one, given as its index and the number of frames),
+ * already shown upright, and align the ones selected
+ */
+#[allow(clippy::too_many_arguments)]
+fn process_frame(
+	path: &Path,
+	img: DynamicImage,
+	frame: Option<(usize, usize)>,
+	rng: Rng,
+	face_detector: &dyn FaceDetector,
+	opt: &Opt,
+	target_faces_rect: WHf,
+	target_eye_distance: f32,
+) -> ProcessedImage {
+	let mut processed_image = ProcessedImage::new(rng);
+	let mut log = String::new();
+	let timings = &mut processed_image.timings;
+
+	if let Some((frame_index, num_frames)) = frame {
+		log.push_str(&format!(", frame {}/{}", frame_index + 1, num_frames));
+	}
+	log.push_str(&format!(", {:?}x{:?}", img.width(), img.height()));
+	if opt.log_level() >= LogLevel::Verbose {
+		// Grayscale, 16-bit, CMYK (when decoding JPEGs), and transparent images are all converted to 8-bit RGB
+		// (transparent ones can keep their alpha for the layers)
+		log.push_str(&format!(", {:?}", img.color()));
+	}
+	let image_size: WHf = (img.width() as f32, img.height() as f32);
+	// Faces are detected with transparent areas (e.g. around cut-out faces) showing the background
+	// color, instead of the color values they happen to have. The layers only keep the transparency if
+	// the background isn't opaque, so it shows through them
+	let has_alpha = img.color().has_alpha();
+	let keep_alpha = has_alpha && opt.background[3] < 1.0;
+	let (rgb_image, rgba_image) = timings.time(Stage::Decode, || {
+		if has_alpha {
+			let rgba_image = img.into_rgba8();
+			let background = [opt.background[0], opt.background[1], opt.background[2]];
+			(flatten_alpha(&rgba_image, background), keep_alpha.then_some(rgba_image))
+		} else {
+			(img.into_rgb8(), None)
+		}
+	});
+	if opt.dedupe {
+		processed_image.hash = Some(average_hash(&rgb_image, &opt.luma));
+	}
+	let cache_settings = get_cache_settings(opt);
+	let cache_dir = opt.cache_dir.as_ref().filter(|_| frame.is_none());
+	let cached_faces = cache_dir.and_then(|cache_dir| load_faces(cache_dir, path, &cache_settings));
+	let mut faces = match cached_faces {
+		Some(faces) => {
+			log.push_str(", cached");
+			faces
+		}
+		None => {
+			// Large images can be detected at a smaller size, which is faster; faces are then scaled back up
+			let faces = if opt.detect_scale < 1.0 {
+				let detection_size = whf_to_whi((
+					image_size.0 * opt.detect_scale as f32,
+					image_size.1 * opt.detect_scale as f32,
+				));
+				let detection_image = timings.time(Stage::Resize, || {
+					imageops::resize(
+						&rgb_image,
+						detection_size.0.max(1),
+						detection_size.1.max(1),
+						imageops::Triangle,
+					)
+				});
+				let scale = image_size.0 / detection_image.width() as f32;
+				let faces = timings.time(Stage::Detection, || {
+					face_detector.detect(detection_image.into_array3().view().into_dyn()).unwrap()
+				});
+				faces.into_iter().map(|face| scale_face(face, scale)).collect()
+			} else {
+				timings.time(Stage::Detection, || {
+					face_detector.detect(rgb_image.clone().into_array3().view().into_dyn()).unwrap()
+				})
+			};
+			if let Some(cache_dir) = cache_dir {
+				save_faces(cache_dir, path, &cache_settings, &faces);
+			}
+			faces
+		}
+	};
+	let num_faces_detected = faces.len();
+	log.push_str(&format!(", {} faces", num_faces_detected));
+
+	// Discard faces without an area, which some detectors report in edge cases; they can't be scaled
+	faces.retain(|face| face.rect.width > 0.0 && face.rect.height > 0.0);
+	if faces.len() < num_faces_detected {
+		log.push_str(&format!(" ({} rejected for being empty)", num_faces_detected - faces.len()));
+	}
+	let num_faces_with_area = faces.len();
+
+	// Discard faces we're not confident enough about
+	faces.retain(|face| face.confidence >= opt.min_confidence);
+	if faces.len() < num_faces_with_area {
+		log.push_str(&format!(" ({} rejected for low confidence)", num_faces_with_area - faces.len()));
+	}
+
+	// Discard faces too small (usually in the background) or too large (usually too close to the camera)
+	let resolve_size = |size: &SizeUnit| -> WHf {
+		(size.to_pixels(image_size.0 as u32) as f32, size.to_pixels(image_size.1 as u32) as f32)
+	};
+	if let Some(min_face_size) = &opt.min_face_size {
+		let num_faces = faces.len();
+		faces.retain(|face| is_at_least_size(face, resolve_size(min_face_size)));
+		if faces.len() < num_faces {
+			log.push_str(&format!(" ({} rejected for being too small)", num_faces - faces.len()));
+		}
+	}
+	if let Some(max_face_size) = &opt.max_face_size {
+		let num_faces = faces.len();
+		faces.retain(|face| is_at_most_size(face, resolve_size(max_face_size)));
+		if faces.len() < num_faces {
+			log.push_str(&format!(" ({} rejected for being too large)", num_faces - faces.len()));
+		}
+	}
+
+	// Discard faces too close to the edges, since they'd be cut off
+	if let Some(edge_margin) = &opt.edge_margin {
+		let margin: XYf = (
+			edge_margin.to_pixels(image_size.0 as u32) as f32,
+			edge_margin.to_pixels(image_size.1 as u32) as f32,
+		);
+		let num_faces = faces.len();
+		faces.retain(|face| is_away_from_edges(face, image_size, margin));
+		if faces.len() < num_faces {
+			log.push_str(&format!(
+				" ({} rejected for being too close to the edges)",
+				num_faces - faces.len()
+			));
+		}
+	}
+
+	let mut selected_faces = select_faces(&faces, &opt.face_selection, image_size);
+	if opt.max_faces_per_image > 0 && selected_faces.len() > opt.max_faces_per_image as usize {
+		let num_faces = selected_faces.len();
+		keep_top_faces(&mut selected_faces, opt.max_faces_per_image as usize, &opt.face_ranking);
+		log.push_str(&format!(
+			" ({} dropped for being over the maximum per image)",
+			num_faces - selected_faces.len()
+		));
+	}
+	processed_image.is_valid = true;
+	processed_image.num_faces_detected = num_faces_detected;
+	processed_image.num_faces_accepted = faces.len();
+	processed_image.num_faces_selected = selected_faces.len();
+	if selected_faces.is_empty() {
+		log.push_str("; no valid faces, skipping.");
+	} else if opt.dry_run {
+		log.push_str(&format!("; {} faces would be used.", selected_faces.len()));
+	} else {
+		// Layers are kept with an alpha channel, so the areas outside the image (e.g. after rotating it)
+		// are transparent
+		let source_image = rgba_image.unwrap_or_else(|| DynamicImage::ImageRgb8(rgb_image).into_rgba8());
+		for face in selected_faces {
+			// Has a valid face
+			let mut face_log = format!(", confidence {:?}", face.confidence);
+			if opt.log_level() >= LogLevel::Verbose {
+				face_log.push_str(&format!(
+					", at ({:.0}, {:.0}) with size {:.0}x{:.0}",
+					face.rect.x, face.rect.y, face.rect.width, face.rect.height
+				));
+			}
+			// A single scale is used as it is, so it doesn't change the random values picked for a seed
+			let face_scale = match opt.face_scale.as_slice() {
+				[single] if single.value.0 == single.value.1 => single.value.0 as f32,
+				_ => {
+					let face_scale =
+						get_random_range_weighted(&mut processed_image.rng, &opt.face_scale) as f32;
+					face_log.push_str(&format!(", face scale {:.2}", face_scale));
+					face_scale
+				}
+			};
+			let scale_jitter = if opt.scale_jitter.is_empty() {
+				1.0
+			} else {
+				let scale_jitter =
+					get_random_range_weighted(&mut processed_image.rng, &opt.scale_jitter) as f32;
+				face_log.push_str(&format!(", scale jitter {:.2}", scale_jitter));
+				scale_jitter
+			};
+			let (image, anchor, size) = timings.time(Stage::Resize, || {
+				align_face(
+					&source_image,
+					face,
+					opt,
+					target_faces_rect,
+					target_eye_distance,
+					face_scale * scale_jitter,
+					&mut face_log,
+				)
+			});
+			processed_image.faces.push(AlignedFace {
+				image,
+				anchor,
+				rect: (face.rect.x, face.rect.y, face.rect.width, face.rect.height),
+				size,
+				confidence: face.confidence,
+				log: face_log,
+			});
+		}
+	}
+
+	processed_image.log = log;
+	processed_image
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "face-stack", about = "Stacks face-aligned images.")]
 struct Opt {
-	/// File mask (e.g., "images/*.jpg")
-	#[structopt(long, default_value = "*.jpg")]
-	input: String,
+	/// Configuration file to read options from (e.g., "face-stack.toml"), a TOML file with options named like the command line ones (e.g., `crop_width = "50%"`, `opacity = ["0.5", "0.8-0.9@2"]`, `recursive = true`). Options in the command line override the ones in the file
+	#[structopt(long, parse(from_os_str))]
+	config: Option<PathBuf>,
+
+	/// File mask (e.g., "images/*.jpg"); matching ignores case, and `**` matches any number of subdirectories (e.g., "images/**/*.jpg"). Accepts multiple masks (e.g., "images/*.jpg" "others/*.png"). Defaults to "*.jpg", unless `--input-list` is used
+	#[structopt(long)]
+	input: Vec<String>,
+
+	/// Text file with a list of files to use as input, one per line (e.g., "files.txt"), or "-" to read it from the standard input. Blank lines and lines starting with "#" are skipped. Can be combined with `--input`
+	#[structopt(long, parse(from_os_str))]
+	input_list: Option<PathBuf>,
+
+	/// Which frames to use from animated GIF files
+	/// Possible values: `first`, `middle`, `all` (use every frame as a separate image, with its own faces and random values; each frame counts for `--max-images`)
+	#[structopt(long, default_value = "first")]
+	frame_strategy: FrameStrategy,
 
-	/// Output image dimensions (e.g., "800x600")
-	#[structopt(long, default_value = "1024x1024", parse(try_from_str = parse_image_dimensions))]
-	size: (u32, u32),
+	/// Also read files matching the input file mask in all subdirectories of its directory
+	#[structopt(long)]
+	recursive: bool,
 
-	/// Scale of the face (e.g., "0.5")
-	#[structopt(long, default_value = "1")]
-	face_scale: f32,
+	/// Directory where the faces detected in each file are saved, so they don't have to be detected again in later runs. Faces are detected again when files change
+	#[structopt(long, parse(from_os_str))]
+	cache_dir: Option<PathBuf>,
 
-	/// Output file name (e.g., "output.png")
+	/// Skip input images that look the same as (or very similar to) an image read before, like exact duplicates or burst shots, comparing a perceptual hash of each image
+	#[structopt(long)]
+	dedupe: bool,
+
+	/// Maximum number of different bits (0-64) between the perceptual hashes of two images for them to be considered duplicates with `--dedupe`; higher also skips images that are less similar
+	#[structopt(long, default_value = "4")]
+	dedupe_distance: u32,
+
+	/// Only detect and select faces, and report how many were found, without creating the output image
+	#[structopt(long)]
+	dry_run: bool,
+
+	/// Print details of each file and face being processed, instead of a progress bar
+	#[structopt(long)]
+	verbose: bool,
+
+	/// Only print errors, warnings, and the final summary
+	#[structopt(long, conflicts_with = "verbose")]
+	quiet: bool,
+
+	/// Print how long the run took at the end, and how much of it was spent in each stage (finding files, decoding images, detecting faces, resizing, and blending)
+	#[structopt(long)]
+	timings: bool,
+
+	/// Don't rotate or flip input images according to their EXIF orientation tag
+	#[structopt(long)]
+	ignore_exif: bool,
+
+	/// Order in which input files are used
+	/// Possible values: `name` (by path), `name-desc` (by path, in reverse), `none` (as found in the file system, which can differ between machines), `random` (shuffled based on the seed)
+	#[structopt(long, default_value = "name")]
+	sort: SortOrder,
+
+	/// Output image dimensions (e.g., "800x600"). Accepts multiple sizes (e.g., "1024x1024,2048x2048"), saving one output for each; the image is made at the largest size, and resized (and cropped, if the aspect ratio is different) for the others
+	#[structopt(long, use_delimiter = true, default_value = "1024x1024", parse(try_from_str = parse_image_dimensions))]
+	size: Vec<(u32, u32)>,
+
+	/// Scale of the face (e.g., "0.5"); multiplies `--face-fill`. Accepts ranges and multiple values (e.g., "0.8-1.2@2 1"), picking a scale for each layer
+	#[structopt(long, use_delimiter = true, default_value = "1", parse(try_from_str = parse_weighted_float_pair))]
+	face_scale: Vec<WeightedValue<(f64, f64)>>,
+
+	/// Fraction of the output image (0-1) that a typical face fills, fitting its width or height (e.g., "0.4" for more room around the face); multiplied by `--face-scale`
+	#[structopt(long, default_value = "0.6", parse(try_from_str = parse_downscale))]
+	face_fill: f64,
+
+	/// Horizontal position, as a fraction of the output width (0-1), where the center of each face (see `--center-on`) is placed
+	#[structopt(long, default_value = "0.5", parse(try_from_str = parse_anchor))]
+	anchor_x: f32,
+
+	/// Vertical position, as a fraction of the output height (0-1), where the center of each face (see `--center-on`) is placed (e.g. "0.4" to leave more room for the chin)
+	#[structopt(long, default_value = "0.5", parse(try_from_str = parse_anchor))]
+	anchor_y: f32,
+
+	/// Output file name (e.g., "output.png"). With a ".gif" file, it's saved as an animation of the image being built, one layer at a time. With multiple `--size` values, "{w}" and "{h}" in the name are replaced with each size (e.g., "output_{w}x{h}.png"); otherwise, the size is added before the extension
 	#[structopt(long, default_value = "face-stack-output.jpg", parse(from_os_str))]
 	output: PathBuf,
 
-	/// The seed to use for the pseudorandom number generator, between `1` and `4294967295`
+	/// Stop with an error before doing anything if the output file already exists, instead of overwriting it
+	#[structopt(long)]
+	no_clobber: bool,
+
+	/// Overwrite the output file even when using `--no-clobber`
+	#[structopt(long)]
+	force: bool,
+
+	/// File to write a JSON record of the run to (e.g., "output.json"), with the seed, the input files, and the settings picked for each layer
+	#[structopt(long, parse(from_os_str))]
+	metadata: Option<PathBuf>,
+
+	/// File to save a grid with a thumbnail of each layer to (e.g., "faces.jpg"), to quickly check the faces used
+	#[structopt(long, parse(from_os_str))]
+	contact_sheet: Option<PathBuf>,
+
+	/// File to save the combined values of all layers to, as they are (as 32-bit floats, before any color adjustment, and in linear light only when using `--linear-light`), for debugging (e.g., "debug.exr"); always saved as OpenEXR, independently of `--format`
+	#[structopt(long, parse(from_os_str))]
+	debug_accumulator: Option<PathBuf>,
+
+	/// Directory to save the output image to after each layer is painted (as "frame_00001.png", etc), to make an animation of it being built. Each frame is a full PNG image, so this can take a lot of disk space. Frames are saved as painted, before `--white-balance` and `--normalize` are applied
+	#[structopt(long, parse(from_os_str))]
+	frames_dir: Option<PathBuf>,
+
+	/// Only save a frame to `--frames-dir` (or to an animated output) after every this many layers
+	#[structopt(long, default_value = "1", parse(try_from_str = parse_positive_integer))]
+	frames_every: u32,
+
+	/// Frames per second of an animated output
+	#[structopt(long, default_value = "10", parse(try_from_str = parse_positive_integer))]
+	fps: u32,
+
+	/// The seed to use for the pseudorandom number generator, between `1` and `18446744073709551615`
 	#[structopt(long, default_value = "0")]
-	seed: u32,
+	seed: u64,
 
 	/// Opacity for each new layer when blending images
-	#[structopt(long, default_value = "0.5", parse(try_from_str = parse_weighted_float_pair))]
+	#[structopt(long, use_delimiter = true, default_value = "0.5", parse(try_from_str = parse_weighted_float_pair))]
 	opacity: Vec<WeightedValue<(f64, f64)>>,
 
-	/// Width for the crop rectangle of new blended layes
-	#[structopt(long, default_value = "0%-100%", parse(try_from_str = parse_weighted_size_pair))]
+	/// How the opacity of each layer decreases as more layers are painted, so later layers don't cover earlier ones
+	/// Possible values: `none`, `average` (divided by the number of layers painted so far, including this one; with `--opacity 1` and the `normal` blending mode, every layer ends up with the same weight, like an average), or a factor (e.g. "0.95"; multiplied by it once for each layer painted before)
+	#[structopt(long, default_value = "none", parse(try_from_str = parse_opacity_decay))]
+	opacity_decay: OpacityDecay,
+
+	/// Weights of the red, green, and blue channels used for the brightness of colors, in everything based on it (`--desaturate`, `--tint`, `--dedupe`, and the `hue`, `saturation`, `color`, `luminosity`, `darker-color`, and `lighter-color` blending modes). The `hue`, `saturation`, `color`, and `luminosity` modes are defined with weights close to `rec601`, which gives results closer to other tools
+	/// Possible values: `rec709` (HDTV and sRGB), `rec601` (SDTV, and most older tools), `average` (the same weight for each channel)
+	#[structopt(long, default_value = "rec709")]
+	luma: Luma,
+
+	/// Opacity for each new layer, per channel (e.g. "1/0.5-0.6/0.5"); overrides `--opacity` when present
+	#[structopt(long, use_delimiter = true, parse(try_from_str = parse_weighted_float_pair_triple))]
+	opacity_rgb: Vec<WeightedValue<[(f64, f64); 3]>>,
+
+	/// Multiply the opacity of each layer by the confidence of its face detection, so uncertain faces are more subtle. This is applied after picking the opacity from `--opacity` or `--opacity-rgb`, so a layer with a picked opacity of 0.8 and a confidence of 0.5 is blended at 0.4
+	#[structopt(long)]
+	confidence_weighting: bool,
+
+	/// Chance (0-1) of mirroring each layer horizontally, for more symmetric results
+	#[structopt(long, default_value = "0", parse(try_from_str = parse_amount))]
+	flip_chance: f32,
+
+	/// Invert the colors of every layer before blending it; looks best with modes like `difference` and `exclusion`
+	#[structopt(long)]
+	invert_layers: bool,
+
+	/// Chance (0-1) of inverting the colors of each layer before blending it, like `--invert-layers` does for all of them
+	#[structopt(long, default_value = "0", parse(try_from_str = parse_amount))]
+	invert_chance: f32,
+
+	/// Amount (0-1) to desaturate each layer by before blending it, from no change (0) to grayscale (1), for black-and-white results from color images. With fully grayscale layers, the `hue`, `saturation`, and `color` blending modes also remove the color of the layers below, while `luminosity` keeps it
+	#[structopt(long, default_value = "0", parse(try_from_str = parse_amount))]
+	desaturate: f32,
+
+	/// Color(s) to tint each layer with before blending it, with an optional strength from 0 to 1 (e.g. "#f80", "#704214/0.5"), for duotone-like results; with multiple values (e.g. "#f80 #08f@2"), one is picked for each layer. Shadows stay dark, and highlights take the color
+	#[structopt(long, use_delimiter = true, parse(try_from_str = parse_weighted_tint))]
+	tint: Vec<WeightedValue<([f32; 3], f32)>>,
+
+	/// Multiplier for the scale of each layer (e.g. "0.9-1.1"); values far from 1 make faces intentionally misaligned
+	#[structopt(long, use_delimiter = true, parse(try_from_str = parse_weighted_float_pair))]
+	scale_jitter: Vec<WeightedValue<(f64, f64)>>,
+
+	/// Angle, in degrees, to rotate each layer by (clockwise) around its face (e.g. "-5-5"; use "--rotation=-5-5" when the value starts with "-")
+	#[structopt(long, use_delimiter = true, parse(try_from_str = parse_weighted_signed_float_pair))]
+	rotation: Vec<WeightedValue<(f64, f64)>>,
+
+	/// Width for the crop rectangle of new blended layes (e.g. "200" for pixels, "50%" of the output width, or "2f" for twice the face width)
+	#[structopt(long, use_delimiter = true, default_value = "0%-100%", parse(try_from_str = parse_weighted_size_pair))]
 	crop_width: Vec<WeightedValue<(SizeUnit, SizeUnit)>>,
 
-	/// Height for the crop rectangle of new blended layes
-	#[structopt(long, default_value = "0%-100%", parse(try_from_str = parse_weighted_size_pair))]
+	/// Height for the crop rectangle of new blended layes (e.g. "200" for pixels, "50%" of the output height, or "2f" for twice the face height)
+	#[structopt(long, use_delimiter = true, default_value = "0%-100%", parse(try_from_str = parse_weighted_size_pair))]
 	crop_height: Vec<WeightedValue<(SizeUnit, SizeUnit)>>,
 
+	/// Shape of the area of each layer that gets painted, inside its crop rectangle
+	/// Possible values: `rect` (the whole crop rectangle), `ellipse` (an ellipse inscribed in the crop rectangle)
+	#[structopt(long, default_value = "rect")]
+	mask_shape: MaskShape,
+
+	/// Where to place the crop rectangle of each layer
+	/// Possible values: `random` (anywhere inside the output image), `face` (centered on the face, at the anchor)
+	#[structopt(long, default_value = "random")]
+	crop_anchor: CropAnchor,
+
+	/// Distance from the edges of the crop rectangle over which each layer fades in (e.g. "20" for pixels, "10%" of the crop rectangle's smaller side, or "0.2f" of the face's smaller side), to avoid visible seams
+	#[structopt(long, default_value = "0", parse(try_from_str = parse_face_size))]
+	feather: SizeUnit,
+
+	/// Fade out each layer away from the center of its face, reaching zero at a radius (e.g. "300" for pixels, "50%" of the output's smaller side, or "1.5f" of the face's smaller side); an optional strength after a `/` (e.g. "50%/2", default 1) makes the fade start closer to the center
+	#[structopt(long, parse(try_from_str = parse_vignette))]
+	vignette: Option<(SizeUnit, f64)>,
+
 	/// Blending mode(s) to be used when overlaying images
-	/// Possible values: `normal`, `multiply`, `screen`, `overlay`, `darken`, `lighten`, `color-dodge`, `color-burn`, `hard-light`, `soft-light`, `difference`, `exclusion`
-	#[structopt(long, default_value = "normal", default_value = "normal", parse(try_from_str = parse_weighted_blending_mode))]
-	blending_mode: Vec<WeightedValue<BlendingMode>>,
+	/// Possible values: see `--list-blending-modes`; the groups `darkening` (`multiply`, `darken`, `color-burn`, `linear-burn`) and `lightening` (`screen`, `lighten`, `color-dodge`, `linear-dodge`) can also be used
+	/// Note that `average` only averages each new layer with the current stack, so later layers weigh more; even with `--opacity 1.0`, stacking N images does not produce their true mean
+	#[structopt(long, use_delimiter = true, default_value = "normal", default_value = "normal", parse(try_from_str = parse_weighted_blending_modes))]
+	blending_mode: Vec<Vec<WeightedValue<BlendingMode>>>,
+
+	/// How the blending mode of each layer is picked, when using multiple blending modes
+	/// Possible values: `weighted-random` (picked at random for each layer, so some modes can end up used more than their weights say), `cycle` (used in order, each one as many times in a row as its weight), `shuffle` (like drawing cards from a deck with each mode as many times as its weight, shuffled again when it runs out; modes end up used a balanced number of times)
+	#[structopt(long, default_value = "weighted-random")]
+	blend_order: BlendOrder,
 
-	/// Number of maximum valid images to use for input
+	/// Print all possible blending modes, and exit
+	#[structopt(long)]
+	list_blending_modes: bool,
+
+	/// Color the output image starts with, before anything is blended on it (e.g. "black", "#fff", "#202020"). Use "transparent" for an output with transparency, where only the areas covered by layers are visible
+	#[structopt(long, default_value = "gray", parse(try_from_str = parse_background))]
+	background: [f32; 4],
+
+	/// Image the output image starts with, resized to fit it, instead of a plain `--background` color
+	#[structopt(long, parse(from_os_str))]
+	background_image: Option<PathBuf>,
+
+	/// How to fit the `--background-image` to the output image: "cover" fills it, cropping the image if needed; "contain" shows the whole image, with the `--background` color around it
+	#[structopt(long, default_value = "cover", possible_values = &["cover", "contain"])]
+	background_fit: ImageFit,
+
+	/// Number of maximum valid images to use for input (or faces, with `--face-selection all`); files are read in order until reaching it, so it only uses the first files found
 	#[structopt(long, default_value = "0")]
 	max_images: u32,
+
+	/// Number of input files to pick at random (based on the seed) from all the files found, before reading any of them. Unlike `--max-images`, this counts files that end up skipped (e.g. without faces), but picks them from the whole set
+	#[structopt(long, default_value = "0")]
+	sample: u32,
+
+	/// Blend in linear light instead of directly on the sRGB-encoded values; makes modes like `multiply` and `screen` look less muddy
+	#[structopt(long)]
+	linear_light: bool,
+
+	/// Combine all layers at once at the end, instead of blending each one on top of the previous ones; ignores opacity and blending modes
+	/// Possible values: `mean` (average of all layers painted on each pixel), `median` (median of all layers painted on each pixel; needs to keep every layer in memory, so consider capping it with `--max-images`), `max` (brightest value of each channel), `min` (darkest value of each channel)
+	#[structopt(long)]
+	stack_mode: Option<StackMode>,
+
+	/// Which face to use from each image
+	/// Possible values: `single` (skip images that don't have exactly one face), `largest` (the face with the largest area), `most-confident` (the face detected with the highest confidence), `center` (the face closest to the center of the image), `all` (every face, each as its own layer)
+	#[structopt(long, default_value = "single")]
+	face_selection: FaceSelection,
+
+	/// Number of maximum faces to use from each image, so a crowd photo can't dominate the stack; `0` means no limit. Applied after selection, so it only matters with `--face-selection all`; the faces kept are the top ones according to `--face-ranking`
+	#[structopt(long, default_value = "0")]
+	max_faces_per_image: u32,
+
+	/// How to pick the top faces to keep with `--max-faces-per-image`; ties keep the order in which faces were detected
+	/// Possible values: `confidence` (the faces detected with the highest confidence), `size` (the faces with the largest area)
+	#[structopt(long, default_value = "confidence")]
+	face_ranking: FaceRanking,
+
+	/// Minimum detection confidence (0-1) for a face to be used; faces below it are discarded before selection
+	#[structopt(long, default_value = "0", parse(try_from_str = parse_amount))]
+	min_confidence: f32,
+
+	/// Minimum size of a face in its image (e.g. "100" for pixels, or "10%" of the image size); smaller faces are discarded before selection
+	#[structopt(long, parse(try_from_str = parse_size))]
+	min_face_size: Option<SizeUnit>,
+
+	/// Maximum size of a face in its image (e.g. "1000" for pixels, or "80%" of the image size); larger faces are discarded before selection
+	#[structopt(long, parse(try_from_str = parse_size))]
+	max_face_size: Option<SizeUnit>,
+
+	/// Minimum distance between a face and the edges of its image (e.g. "20" for pixels, or "5%" of the image size); faces closer to the edges are discarded before selection
+	#[structopt(long, parse(try_from_str = parse_size))]
+	edge_margin: Option<SizeUnit>,
+
+	/// Face detector to use
+	/// Possible values: `blazeface`, `mtcnn`
+	#[structopt(long, default_value = "blazeface")]
+	detector: Detector,
+
+	/// Where to run face detection. Other than `cpu`, providers need ONNX Runtime to have been built with support for them (see the README for the `ort` features each one needs); if one can't be used, detection falls back to the CPU
+	/// Possible values: `cpu`, `cuda`, `coreml`, `directml` (rust-faces has no DirectML provider yet, so this always falls back to the CPU)
+	#[structopt(long, default_value = "cpu")]
+	provider: InferenceProvider,
+
+	/// Number of threads to use for detecting faces in each file; `0` lets ONNX Runtime decide. Files are already processed in parallel, one per logical CPU, so this defaults to 1 (or the number of logical CPUs per file, if fewer files are processed at once, like with `RAYON_NUM_THREADS`); higher values use more threads than there are CPUs
+	#[structopt(long)]
+	threads: Option<u32>,
+
+	/// Size of the image used internally by the `blazeface` face detector; smaller is faster but less accurate. The default from the detector is 1280, but it finds no faces; 80 works too
+	#[structopt(long, default_value = "160", parse(try_from_str = parse_positive_integer))]
+	detector_target_size: u32,
+
+	/// Minimum score (0-1) for the `blazeface` face detector to report a face; lower finds more faces, but also more false positives
+	#[structopt(long, default_value = "0.95", parse(try_from_str = parse_threshold))]
+	score_threshold: f32,
+
+	/// Overlap (0-1, as intersection over union) above which the `blazeface` face detector merges two detected faces into one; lower avoids duplicated faces, but can merge faces that are close together
+	#[structopt(long, default_value = "0.3", parse(try_from_str = parse_threshold))]
+	nms_threshold: f32,
+
+	/// Minimum size, in pixels, of the faces searched for by the `mtcnn` face detector; to discard faces after detection, use `--min-face-size` instead
+	#[structopt(long, default_value = "24", parse(try_from_str = parse_positive_integer))]
+	detector_min_face_size: u32,
+
+	/// Scale to resize input images to before detecting faces (e.g. "0.5"), which makes detection faster for large images. Faces are still aligned and blended from the full size images
+	#[structopt(long, default_value = "1", parse(try_from_str = parse_downscale))]
+	detect_scale: f64,
+
+	/// Format of the output image (e.g. "png", "jpg", "exr", "hdr"); defaults to the one from the `--output` file extension
+	/// Float formats (`exr`, `hdr`) are saved without quantization and in linear light, for further grading in compositing software
+	#[structopt(long, parse(try_from_str = parse_image_format))]
+	format: Option<ImageFormat>,
+
+	/// Bits per channel of the output image: `8` or `16`; 16 bits avoid banding in smooth gradients, but need a format that supports it, like PNG
+	#[structopt(long, default_value = "8", parse(try_from_str = parse_bit_depth))]
+	bit_depth: u32,
+
+	/// Quality of the output image when saving it as a JPEG file, from 1 to 100; the default is 75
+	#[structopt(long, parse(try_from_str = parse_quality))]
+	jpeg_quality: Option<u8>,
+
+	/// Quality of the output image when saving it as a WebP file, from 1 to 100; the default is 75
+	#[structopt(long, parse(try_from_str = parse_quality))]
+	webp_quality: Option<u8>,
+
+	/// Save WebP files lossless, instead of with --webp-quality
+	#[structopt(long)]
+	lossless: bool,
+
+	/// Dither the output image when converting it to 8 or 16 bits per channel, to avoid banding in smooth gradients
+	#[structopt(long)]
+	dither: bool,
+
+	/// How to correct the color cast of the output image, which can come from mixing photos with different lighting. With a `.gif` output, each frame is corrected on its own
+	/// Possible values: `none`, `gray-world` (scale each channel so the average color is a neutral gray)
+	#[structopt(long, default_value = "none")]
+	white_balance: WhiteBalance,
+
+	/// Stretch the levels of each channel of the output image, so its darkest and brightest colors become black and white; useful when stacking at a low opacity makes it look washed out. With a `.gif` output, each frame is stretched on its own
+	#[structopt(long)]
+	normalize: bool,
+
+	/// Percentage of the darkest and brightest values of each channel to ignore when using `--normalize`, so a few extreme pixels don't limit it
+	#[structopt(long, default_value = "0.5", parse(try_from_str = parse_percentage))]
+	normalize_clip: f32,
+
+	/// Stop with an error when a layer can't be painted because it falls outside the output image, instead of skipping it
+	#[structopt(long)]
+	strict: bool,
+
+	/// Rotate each image so the line between the eyes is horizontal; only works when the detector finds the eyes
+	#[structopt(long)]
+	align_rotation: bool,
+
+	/// How to align and scale each face
+	/// Possible values: `bbox` (center and size of the detected face rectangle), `eyes` (position and distance between the eyes, which is more consistent; falls back to `bbox` when the detector doesn't find the eyes)
+	#[structopt(long, default_value = "bbox")]
+	align_by: AlignBy,
+
+	/// Which point of each face goes at the anchor of the output image (`--anchor-x`, `--anchor-y`); defaults to the one from `--align-by`
+	/// Possible values: `bbox` (center of the detected face rectangle), `eyes` (middle point between the eyes), `nose` (tip of the nose); `eyes` and `nose` fall back to `bbox` when the detector doesn't find them
+	#[structopt(long)]
+	center_on: Option<CenterOn>,
+}
+
+/**
+ * Get the glob pattern to read input files from. When recursive, files are also searched for in all
+ * subdirectories of the pattern's directory, by adding a `**` component before the file name
+ */
+fn get_input_pattern(input: &str, recursive: bool) -> String {
+	if !recursive || input.contains("**") {
+		return input.to_string();
+	}
+	let input_path = Path::new(input);
+	match (input_path.parent(), input_path.file_name()) {
+		(Some(parent), Some(file_name)) => parent.join("**").join(file_name).to_string_lossy().to_string(),
+		_ => input.to_string(),
+	}
+}
+
+/**
+ * Save the output image. Float formats (OpenEXR and Radiance HDR) get the unquantized values in linear light;
+ * other formats are encoded to sRGB, and only use 16 bits per channel if the format supports it. Only keeps
+ * the alpha channel if `keep_alpha` is set and the format supports it. If `--dither` is used, the
+ * quantization to integer formats is dithered. WebP files are saved with libwebp, lossy unless `--lossless` is
+ * used
+ */
+fn save_output(image: &Rgba32FImage, path: &Path, opt: &Opt, keep_alpha: bool) {
+	let linear_light = opt.linear_light;
+	let format = opt
+		.format
+		.or_else(|| ImageFormat::from_path(path).ok())
+		.expect("Could not determine the output format; use a known file extension, or --format");
+	let supports_alpha = !matches!(format, ImageFormat::Jpeg | ImageFormat::Hdr | ImageFormat::Pnm);
+	let supports_16_bits = matches!(format, ImageFormat::Png | ImageFormat::Tiff);
+	let is_float = matches!(format, ImageFormat::OpenExr | ImageFormat::Hdr);
+	if keep_alpha && !supports_alpha {
+		eprintln!("Warning: the output format doesn't support transparency; use a .png file to keep it.");
+	}
+	if opt.bit_depth == 16 && !supports_16_bits && !is_float {
+		eprintln!(
+			"Warning: the output format doesn't support 16 bits per channel; use a .png file to keep it."
+		);
+	}
+	if opt.jpeg_quality.is_some() && format != ImageFormat::Jpeg {
+		eprintln!("Warning: --jpeg-quality is only used for JPEG files; ignoring it.");
+	}
+	if (opt.webp_quality.is_some() || opt.lossless) && format != ImageFormat::WebP {
+		eprintln!("Warning: --webp-quality and --lossless are only used for WebP files; ignoring them.");
+	} else if opt.webp_quality.is_some() && opt.lossless {
+		eprintln!("Warning: --webp-quality is not used for lossless WebP files; ignoring it.");
+	}
+	let keep_alpha = keep_alpha && supports_alpha;
+
+	if is_float {
+		// Float formats are expected to be in linear light; with alpha, OpenEXR also expects the color to be
+		// premultiplied
+		let linear = ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+			let mut pixel = image.get_pixel(x, y).0;
+			let alpha = if keep_alpha {
+				pixel[3]
+			} else {
+				1.0
+			};
+			for channel in pixel.iter_mut().take(3) {
+				if !linear_light {
+					*channel = srgb_to_linear(*channel);
+				}
+				*channel *= alpha;
+			}
+			Rgba(pixel)
+		});
+		let result = match format {
+			ImageFormat::Hdr => {
+				let pixels: Vec<Rgb<f32>> = linear.pixels().map(|pixel| pixel.to_rgb()).collect();
+				let file = File::create(path).expect("Failed to create output image");
+				HdrEncoder::new(BufWriter::new(file)).encode(
+					&pixels,
+					image.width() as usize,
+					image.height() as usize,
+				)
+			}
+			_ if keep_alpha => DynamicImage::ImageRgba32F(linear).save_with_format(path, format),
+			_ => DynamicImage::ImageRgb32F(DynamicImage::ImageRgba32F(linear).into_rgb32f())
+				.save_with_format(path, format),
+		};
+		result.expect("Failed to save output image");
+		return;
+	}
+
+	let is_16_bits = opt.bit_depth == 16 && supports_16_bits;
+	let encoded = encode_image(image, linear_light, is_16_bits, keep_alpha, opt.dither);
+	let result = match (format, opt.jpeg_quality) {
+		(ImageFormat::Jpeg, Some(quality)) => {
+			let file = File::create(path).expect("Failed to create output image");
+			JpegEncoder::new_with_quality(BufWriter::new(file), quality).encode_image(&encoded)
+		}
+		(ImageFormat::WebP, _) => {
+			// The image crate can only save lossless WebP files, so they're encoded with libwebp
+			let (width, height) = (encoded.width(), encoded.height());
+			let encoder = if keep_alpha {
+				Encoder::from_rgba(encoded.as_bytes(), width, height)
+			} else {
+				Encoder::from_rgb(encoded.as_bytes(), width, height)
+			};
+			let quality = opt.webp_quality.unwrap_or(75) as f32;
+			let webp = encoder.encode_simple(opt.lossless, quality).expect("Failed to encode output image");
+			std::fs::write(path, &*webp).map_err(ImageError::IoError)
+		}
+		_ => encoded.save_with_format(path, format),
+	};
+	result.expect("Failed to save output image");
+}
+
+/**
+ * Adjust the colors of the output image (or of a frame of it), as requested
+ */
+fn adjust_colors(image: &mut Rgba32FImage, opt: &Opt) {
+	if opt.white_balance == WhiteBalance::GrayWorld {
+		balance_gray_world(image);
+	}
+	if opt.normalize {
+		normalize_levels(image, opt.normalize_clip);
+	}
+}
+
+/**
+ * Convert an image from Rgba-32f to sRGB-encoded integers, with 8 or 16 bits per channel, keeping its alpha
+ * channel or not. If `dither` is set, the quantization is dithered
+ */
+fn encode_image(
+	image: &Rgba32FImage,
+	linear_light: bool,
+	is_16_bits: bool,
+	keep_alpha: bool,
+	dither: bool,
+) -> DynamicImage {
+	let mut encoded = ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+		let mut pixel = image.get_pixel(x, y).0;
+		if linear_light {
+			for channel in pixel.iter_mut().take(3) {
+				*channel = linear_to_srgb(*channel);
+			}
+		}
+		Rgba(pixel)
+	});
+	if dither {
+		dither_floyd_steinberg(
+			&mut encoded,
+			if is_16_bits {
+				65535.0
+			} else {
+				255.0
+			},
+		);
+	}
+	let encoded = DynamicImage::ImageRgba32F(encoded);
+	match (is_16_bits, keep_alpha) {
+		(true, true) => DynamicImage::ImageRgba16(encoded.into_rgba16()),
+		(true, false) => DynamicImage::ImageRgb16(encoded.into_rgb16()),
+		(false, true) => DynamicImage::ImageRgba8(encoded.into_rgba8()),
+		(false, false) => DynamicImage::ImageRgb8(encoded.into_rgb8()),
+	}
+}
+
+impl Opt {
+	fn log_level(&self) -> LogLevel {
+		if self.quiet {
+			LogLevel::Quiet
+		} else if self.verbose {
+			LogLevel::Verbose
+		} else {
+			LogLevel::Normal
+		}
+	}
+}
+
+/**
+ * Create the face detector picked in the options. If the inference provider can't be used, falls back to the CPU
+ */
+fn build_face_detector(opt: &Opt, threads: Option<usize>) -> Box<dyn FaceDetector> {
+	let build = |provider: Provider| {
+		let detection = match opt.detector {
+			Detector::BlazeFace => FaceDetection::BlazeFace640(BlazeFaceParams {
+				target_size: opt.detector_target_size as usize,
+				score_threshold: opt.score_threshold,
+				nms: Nms {
+					iou_thres: opt.nms_threshold,
+				},
+				..Default::default()
+			}),
+			Detector::MtCnn => FaceDetection::MtCnn(MtCnnParams {
+				min_face_size: opt.detector_min_face_size as usize,
+				..Default::default()
+			}),
+		};
+		FaceDetectorBuilder::new(detection)
+			.download()
+			.infer_params(InferParams {
+				provider,
+				intra_threads: threads,
+				..Default::default()
+			})
+			.build()
+	};
+
+	let face_detector = match opt.provider.to_provider() {
+		Some(provider) => build(provider).map_err(|err| err.to_string()),
+		None => Err("not supported by rust-faces".to_string()),
+	};
+	match face_detector {
+		Ok(face_detector) => face_detector,
+		Err(err) if opt.provider != InferenceProvider::Cpu => {
+			eprintln!(
+				"Warning: could not use the {} provider ({}); falling back to the CPU.",
+				opt.provider, err
+			);
+			build(Provider::OrtCpu).expect("Failed to load the face detector")
+		}
+		Err(err) => panic!("Failed to load the face detector: {}", err),
+	}
+}
+
+/**
+ * Read the options from the command line and, if `--config` is used, from a configuration file. Options in the
+ * command line override the ones in the file
+ */
+fn get_options() -> Opt {
+	let matches = Opt::clap().get_matches();
+	let mut opt = Opt::from_clap(&matches);
+	let mut config = match &opt.config {
+		Some(config_path) => {
+			Config::read(config_path).unwrap_or_else(|err| panic!("Failed to read the config file: {}", err))
+		}
+		None => return opt,
+	};
+	config.remove_if(|name| name == "config" || matches.occurrences_of(name) > 0);
+	merge_config(&mut opt, &mut config)
+		.unwrap_or_else(|err| panic!("Failed to read the config file: {}", err));
+	opt
+}
+
+/**
+ * Merge the options in a configuration file into the ones from the command line, parsing them like the command
+ * line does. Fails if a value is not valid, or if an option is not known
+ */
+fn merge_config(opt: &mut Opt, config: &mut Config) -> Result<(), String> {
+	config.merge_list("input", &mut opt.input, str::parse)?;
+	config.merge_option("input-list", &mut opt.input_list, str::parse)?;
+	config.merge_value("frame-strategy", &mut opt.frame_strategy, str::parse)?;
+	config.merge_flag("recursive", &mut opt.recursive)?;
+	config.merge_option("cache-dir", &mut opt.cache_dir, str::parse)?;
+	config.merge_flag("dedupe", &mut opt.dedupe)?;
+	config.merge_value("dedupe-distance", &mut opt.dedupe_distance, str::parse)?;
+	config.merge_flag("dry-run", &mut opt.dry_run)?;
+	config.merge_flag("verbose", &mut opt.verbose)?;
+	config.merge_flag("quiet", &mut opt.quiet)?;
+	config.merge_flag("timings", &mut opt.timings)?;
+	config.merge_flag("ignore-exif", &mut opt.ignore_exif)?;
+	config.merge_value("sort", &mut opt.sort, str::parse)?;
+	config.merge_required_values("size", &mut opt.size, parse_image_dimensions)?;
+	config.merge_required_values("face-scale", &mut opt.face_scale, parse_weighted_float_pair)?;
+	config.merge_value("face-fill", &mut opt.face_fill, parse_downscale)?;
+	config.merge_value("anchor-x", &mut opt.anchor_x, parse_anchor)?;
+	config.merge_value("anchor-y", &mut opt.anchor_y, parse_anchor)?;
+	config.merge_value("output", &mut opt.output, str::parse)?;
+	config.merge_flag("no-clobber", &mut opt.no_clobber)?;
+	config.merge_flag("force", &mut opt.force)?;
+	config.merge_option("metadata", &mut opt.metadata, str::parse)?;
+	config.merge_option("contact-sheet", &mut opt.contact_sheet, str::parse)?;
+	config.merge_option("debug-accumulator", &mut opt.debug_accumulator, str::parse)?;
+	config.merge_option("frames-dir", &mut opt.frames_dir, str::parse)?;
+	config.merge_value("frames-every", &mut opt.frames_every, parse_positive_integer)?;
+	config.merge_value("fps", &mut opt.fps, parse_positive_integer)?;
+	config.merge_value("seed", &mut opt.seed, str::parse)?;
+	config.merge_required_values("opacity", &mut opt.opacity, parse_weighted_float_pair)?;
+	config.merge_value("opacity-decay", &mut opt.opacity_decay, parse_opacity_decay)?;
+	config.merge_value("luma", &mut opt.luma, str::parse)?;
+	config.merge_values("opacity-rgb", &mut opt.opacity_rgb, parse_weighted_float_pair_triple)?;
+	config.merge_flag("confidence-weighting", &mut opt.confidence_weighting)?;
+	config.merge_value("flip-chance", &mut opt.flip_chance, parse_amount)?;
+	config.merge_flag("invert-layers", &mut opt.invert_layers)?;
+	config.merge_value("invert-chance", &mut opt.invert_chance, parse_amount)?;
+	config.merge_value("desaturate", &mut opt.desaturate, parse_amount)?;
+	config.merge_values("tint", &mut opt.tint, parse_weighted_tint)?;
+	config.merge_values("scale-jitter", &mut opt.scale_jitter, parse_weighted_float_pair)?;
+	config.merge_values("rotation", &mut opt.rotation, parse_weighted_signed_float_pair)?;
+	config.merge_required_values("crop-width", &mut opt.crop_width, parse_weighted_size_pair)?;
+	config.merge_required_values("crop-height", &mut opt.crop_height, parse_weighted_size_pair)?;
+	config.merge_value("mask-shape", &mut opt.mask_shape, str::parse)?;
+	config.merge_value("crop-anchor", &mut opt.crop_anchor, str::parse)?;
+	config.merge_value("feather", &mut opt.feather, parse_face_size)?;
+	config.merge_option("vignette", &mut opt.vignette, parse_vignette)?;
+	config.merge_required_values("blending-mode", &mut opt.blending_mode, parse_weighted_blending_modes)?;
+	config.merge_value("blend-order", &mut opt.blend_order, str::parse)?;
+	config.merge_flag("list-blending-modes", &mut opt.list_blending_modes)?;
+	config.merge_value("background", &mut opt.background, parse_background)?;
+	config.merge_option("background-image", &mut opt.background_image, str::parse)?;
+	config.merge_value("background-fit", &mut opt.background_fit, str::parse)?;
+	config.merge_value("max-images", &mut opt.max_images, str::parse)?;
+	config.merge_value("sample", &mut opt.sample, str::parse)?;
+	config.merge_flag("linear-light", &mut opt.linear_light)?;
+	config.merge_option("stack-mode", &mut opt.stack_mode, str::parse)?;
+	config.merge_value("face-selection", &mut opt.face_selection, str::parse)?;
+	config.merge_value("max-faces-per-image", &mut opt.max_faces_per_image, str::parse)?;
+	config.merge_value("face-ranking", &mut opt.face_ranking, str::parse)?;
+	config.merge_value("min-confidence", &mut opt.min_confidence, parse_amount)?;
+	config.merge_option("min-face-size", &mut opt.min_face_size, parse_size)?;
+	config.merge_option("max-face-size", &mut opt.max_face_size, parse_size)?;
+	config.merge_option("edge-margin", &mut opt.edge_margin, parse_size)?;
+	config.merge_value("detector", &mut opt.detector, str::parse)?;
+	config.merge_value("provider", &mut opt.provider, str::parse)?;
+	config.merge_option("threads", &mut opt.threads, str::parse)?;
+	config.merge_value("detector-target-size", &mut opt.detector_target_size, parse_positive_integer)?;
+	config.merge_value("score-threshold", &mut opt.score_threshold, parse_threshold)?;
+	config.merge_value("nms-threshold", &mut opt.nms_threshold, parse_threshold)?;
+	config.merge_value("detector-min-face-size", &mut opt.detector_min_face_size, parse_positive_integer)?;
+	config.merge_value("detect-scale", &mut opt.detect_scale, parse_downscale)?;
+	config.merge_option("format", &mut opt.format, parse_image_format)?;
+	config.merge_value("bit-depth", &mut opt.bit_depth, parse_bit_depth)?;
+	config.merge_option("jpeg-quality", &mut opt.jpeg_quality, parse_quality)?;
+	config.merge_option("webp-quality", &mut opt.webp_quality, parse_quality)?;
+	config.merge_flag("lossless", &mut opt.lossless)?;
+	config.merge_flag("dither", &mut opt.dither)?;
+	config.merge_value("white-balance", &mut opt.white_balance, str::parse)?;
+	config.merge_flag("normalize", &mut opt.normalize)?;
+	config.merge_value("normalize-clip", &mut opt.normalize_clip, parse_percentage)?;
+	config.merge_flag("strict", &mut opt.strict)?;
+	config.merge_flag("align-rotation", &mut opt.align_rotation)?;
+	config.merge_value("align-by", &mut opt.align_by, str::parse)?;
+	config.merge_option("center-on", &mut opt.center_on, str::parse)?;
+	config.check_all_merged()
 }
 
 fn main() {
-	let opt = Opt::from_args();
-	let (target_width, target_height) = opt.size;
+	let start_time = Instant::now();
+	let opt = get_options();
+	if opt.list_blending_modes {
+		for blending_mode in BlendingMode::iter() {
+			println!("{}", blending_mode);
+		}
+		return;
+	}
+	let log_level = opt.log_level();
+	let (target_width, target_height) =
+		opt.size.iter().copied().max_by_key(|size| size.0 as u64 * size.1 as u64).unwrap();
 
-	println!(
-		"Will get files from {:?}, at size {}x{}, and output at {:?}.",
-		opt.input, target_width, target_height, opt.output
-	);
+	// Decide how many threads to use for inference; `None` lets ONNX Runtime decide. Files are already detected in
+	// parallel, one per thread of the rayon pool, and each detection uses this many threads, so by default the CPUs
+	// are split between them instead of each one trying to use all of them
+	let threads = match opt.threads {
+		Some(0) => None,
+		Some(threads) => Some(threads as usize),
+		None => {
+			let num_cpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+			Some((num_cpus / rayon::current_num_threads()).max(1))
+		}
+	};
+	let threads_info = match threads {
+		Some(threads) => format!("{} threads", threads),
+		None => "threads picked by ONNX Runtime".to_string(),
+	};
 
-	let face_detector =
-        // Alternative:
-        // FaceDetectorBuilder::new(FaceDetection::MtCnn(
-        //     MtCnnParams {
-        //         min_face_size: 1000,
-        //         ..Default::default()
-        //     }))
-        FaceDetectorBuilder::new(FaceDetection::BlazeFace640(
-            BlazeFaceParams {
-                // Default is 1280, but finds no images
-                // 80 works too
-                target_size: 160,
-                ..Default::default()
-            }))
-            .download()
-            .infer_params(InferParams {
-                provider: Provider::OrtCpu,
-                intra_threads: Some(5),
-                ..Default::default()
-            })
-            .build()
-            .expect("Failed to load the face detector");
+	// Without any input, files are picked from the current directory
+	let inputs = if opt.input.is_empty() && opt.input_list.is_none() {
+		vec!["*.jpg".to_string()]
+	} else {
+		opt.input.clone()
+	};
+
+	let detector_info = match opt.detector {
+		Detector::BlazeFace => format!(
+			"target size {}, score threshold {}, NMS threshold {}",
+			opt.detector_target_size, opt.score_threshold, opt.nms_threshold
+		),
+		Detector::MtCnn => format!("minimum face size {}", opt.detector_min_face_size),
+	};
+	let inputs_info = match (&opt.input_list, inputs.is_empty()) {
+		(Some(input_list), true) => format!("the list in {:?}", input_list),
+		(Some(input_list), false) => format!("{:?} and the list in {:?}", inputs, input_list),
+		(None, _) => format!("{:?}", inputs),
+	};
+	// With many sizes, each one gets its own output file
+	let output_paths: Vec<(WHi, PathBuf)> = opt
+		.size
+		.iter()
+		.map(|size| (*size, output_path_for_size(&opt.output, *size, opt.size.len() > 1)))
+		.collect();
+	let sizes_info = opt.size.iter().map(|size| format!("{}x{}", size.0, size.1)).collect::<Vec<String>>();
+	let outputs_info = output_paths.iter().map(|(_, path)| format!("{:?}", path)).collect::<Vec<String>>();
+	if log_level >= LogLevel::Normal {
+		println!(
+			"Will get files from {}, at size {}, and output at {}. Using the {} detector, with {}, on {}.",
+			inputs_info,
+			sizes_info.join(", "),
+			outputs_info.join(", "),
+			opt.detector,
+			detector_info,
+			threads_info
+		);
+	}
+
+	// Check the output before doing any work, so it isn't lost at the end
+	let output_format = opt.format.or_else(|| ImageFormat::from_path(&opt.output).ok());
+	if !opt.dry_run {
+		for (_, output_path) in &output_paths {
+			if opt.no_clobber && !opt.force && output_path.exists() {
+				panic!("The output file {:?} already exists; use --force to overwrite it.", output_path);
+			}
+		}
+		if output_format.is_none() {
+			panic!("Could not determine the output format; use a known file extension, or --format");
+		}
+		let output_files =
+			[opt.metadata.as_ref(), opt.contact_sheet.as_ref(), opt.debug_accumulator.as_ref()];
+		let output_files =
+			output_paths.iter().map(|(_, path)| path).chain(output_files.into_iter().flatten());
+		for output_file in output_files {
+			if let Some(output_dir) = output_file.parent()
+				&& !output_dir.as_os_str().is_empty()
+			{
+				std::fs::create_dir_all(output_dir).expect("Failed to create the output directory");
+			}
+		}
+	}
+
+	let face_detector = build_face_detector(&opt, threads);
+	if let Some(cache_dir) = &opt.cache_dir {
+		std::fs::create_dir_all(cache_dir).expect("Failed to create the cache directory");
+	}
+	if let Some(frames_dir) = &opt.frames_dir {
+		std::fs::create_dir_all(frames_dir).expect("Failed to create the frames directory");
+	}
 
 	// Decide where the face will be in the output image
 	let typical_face_size: WHf = (75f32, 100f32); // Typically 0.75 aspect ratio
 	let faces_rect_inside = fit_inside((target_width as f32, target_height as f32), typical_face_size);
-	let typical_face_scale = 0.6f32 * opt.face_scale;
+	// The face scale is picked for each layer, when aligning its face
+	let typical_face_scale = opt.face_fill as f32;
 	let target_faces_rect: WHf =
 		(faces_rect_inside.0 * typical_face_scale, faces_rect_inside.1 * typical_face_scale);
+	let target_eye_distance = target_faces_rect.0 * 0.45; // Eyes are typically 45% of the face width apart
 
-	// Create the output image
-	let mut output_image: Rgb32FImage =
-		ImageBuffer::from_pixel(target_width, target_height, Rgb([0.5, 0.5, 0.5]));
+	// Create the output image; when blending in linear light, it's kept in linear light until saved
+	let mut background_color = opt.background;
+	if opt.linear_light {
+		for channel in background_color.iter_mut().take(3) {
+			*channel = srgb_to_linear(*channel);
+		}
+	}
+	let is_transparent = background_color[3] < 1.0;
+	let mut output_image: Rgba32FImage =
+		ImageBuffer::from_pixel(target_width, target_height, Rgba(background_color));
+	if let Some(path) = &opt.background_image {
+		let mut img = image::open(path).expect("Could not open the background image");
+		if !opt.ignore_exif
+			&& let Some(orientation) = read_orientation(path)
+		{
+			img = apply_orientation(img, orientation);
+		}
+		let (background_image, offset) =
+			fit_image(&img.into_rgba8(), (target_width, target_height), &opt.background_fit);
+		blend_image(
+			&mut output_image,
+			&background_image,
+			offset,
+			[1.0, 1.0, 1.0],
+			&BlendingMode::Normal,
+			&opt.luma,
+			None,
+			opt.linear_light,
+		);
+	}
+	let mut stack = opt
+		.stack_mode
+		.as_ref()
+		.map(|mode| Stack::new(mode.clone(), target_width, target_height, opt.linear_light));
 	let mut num_images_used = 0usize;
+	let mut num_files_read = 0usize;
 	let mut num_images_read = 0usize;
+	let mut num_layers_skipped = 0usize;
+	let mut metadata_files: Vec<PathBuf> = Vec::new();
+	let mut metadata_layers: Vec<LayerMetadata> = Vec::new();
+	let mut animation = if output_format == Some(ImageFormat::Gif) && !opt.dry_run {
+		// Only the largest size is animated; other sizes get the final image
+		let (_, largest_output_path) =
+			output_paths.iter().find(|(size, _)| *size == (target_width, target_height)).unwrap();
+		Some(Animation::new(largest_output_path, opt.fps))
+	} else {
+		None
+	};
+	let mut contact_sheet =
+		opt.contact_sheet.as_ref().map(|_| ContactSheet::new((target_width, target_height)));
+	// For dry runs: number of files by number of faces detected (0, 1, 2+), and other totals
+	let mut num_files_by_faces = [0usize; 3];
+	let mut num_files_invalid = 0usize;
+	let mut num_files_duplicate = 0usize;
+	let mut seen_hashes: Vec<(u64, PathBuf)> = Vec::new();
+	let mut num_files_usable = 0usize;
+	let mut num_faces_detected = 0usize;
+	let mut num_faces_accepted = 0usize;
 
-	// Creates a random number generator to be used for deterministic randomization
-	let rng_seed = if opt.seed == 0 {
-		Rng::new().next()
+	// Decides the seed for deterministic randomization; each file gets its own generator from it.
+	// When no seed is given, picks one, and reports it so the results can be reproduced
+	let seed = if opt.seed == 0 {
+		Rng::new().seed().max(1)
 	} else {
-		// Seeds close to each other produce very similar results, so we multiply them a bit
-		opt.seed.wrapping_add(Rng::from_seed(1337).next())
+		opt.seed
 	};
-	let mut rng = Rng::from_seed(rng_seed);
-
-	// Reads all images from the given input mask
-	let image_files = glob(&opt.input)
-		.expect(format!("Failed to read glob pattern: {}", opt.input).as_str())
-		.collect::<Vec<Result<PathBuf, GlobError>>>();
-
-	for image_file in &image_files {
-		if let Ok(path) = image_file {
-			// File can be opened
-			terminal::erase_line_to_end();
-			print!(
-				"({}/{}) Reading {:?}",
-				num_images_read + 1,
-				image_files.len(),
-				&path.file_name().unwrap()
-			);
+	if log_level >= LogLevel::Normal {
+		eprintln!("Using seed {}.", seed);
+	}
 
-			if let Ok(img) = image::open(&path) {
-				// Is a valid image file
-				print!(", {:?}x{:?}", img.width(), img.height());
-				let array3_image = img.into_rgb8().into_array3();
-				let faces = face_detector.detect(array3_image.view().into_dyn()).unwrap();
-				print!(", {} faces", faces.len());
-
-				if faces.len() == 1 {
-					// Has a valid face
-					println!(", confidence {:?}", faces[0].confidence);
-
-					let rgb_image = array3_image.to_rgb8();
-					let face_rect = &faces[0].rect;
-
-					// Find out what the face size should be inside our face target box
-					let target_face_rect: WHf =
-						fit_inside(target_faces_rect, (face_rect.width, face_rect.height));
-					let new_image_scale = target_face_rect.0 / face_rect.width;
-					let new_image_size: WHi = whf_to_whi((
-						rgb_image.width() as f32 * new_image_scale,
-						rgb_image.height() as f32 * new_image_scale,
-					));
-
-					// Scale the image appropriately
-					let resized_image =
-						imageops::resize(&rgb_image, new_image_size.0, new_image_size.1, imageops::Lanczos3);
-
-					// Get all the options
-					let param_opacity = get_random_range_weighted(&mut rng, &opt.opacity) as f32;
-					let param_crop_rect = {
-						let crop_width =
-							get_random_size_range_weighted(&mut rng, &opt.crop_width, target_width).round()
-								as u32;
-						let crop_height =
-							get_random_size_range_weighted(&mut rng, &opt.crop_height, target_height).round()
-								as u32;
-						(
-							rng.next_u32_range(0, target_width - crop_width) as i32,
-							rng.next_u32_range(0, target_height - crop_height) as i32,
-							crop_width,
-							crop_height,
-						)
-					};
-					let param_blending_mode = get_random_entry_weighted(&mut rng, &opt.blending_mode);
-					let param_offset: XYi = xyf_to_xyi((
-						target_width as f32 / 2.0 - (face_rect.x + face_rect.width / 2.0) * new_image_scale,
-						target_height as f32 / 2.0 - (face_rect.y + face_rect.height / 2.0) * new_image_scale,
-					));
-
-					// Finally, blend it all
-					blend_image(
-						&mut output_image,
-						&resized_image,
-						param_offset,
-						param_opacity,
-						param_blending_mode,
-						Some(param_crop_rect),
-					);
-
-					num_images_used += 1;
-
-					terminal::cursor_up();
-				} else {
-					println!("; no valid faces, skipping.");
-				}
-			} else {
-				println!("; invalid image, skipping.");
+	// Reads all images from the given input masks; extensions are often in uppercase, so matching ignores case.
+	// Files matched by more than one mask are only used once
+	let match_options = MatchOptions {
+		case_sensitive: false,
+		..Default::default()
+	};
+	let mut timings = Timings::default();
+	let globbing_start = Instant::now();
+	let mut seen_files: HashSet<PathBuf> = HashSet::new();
+	let mut image_files: Vec<Result<PathBuf, GlobError>> = Vec::new();
+	for input in &inputs {
+		let input_pattern = get_input_pattern(input, opt.recursive);
+		let matches = glob_with(&input_pattern, match_options)
+			.expect(format!("Failed to read glob pattern: {}", input_pattern).as_str());
+		for image_file in matches {
+			if let Ok(path) = &image_file
+				&& !seen_files.insert(path.canonicalize().unwrap_or_else(|_| path.clone()))
+			{
+				continue;
 			}
+			image_files.push(image_file);
 		}
+	}
+	if let Some(input_list) = &opt.input_list {
+		let listed_files = read_file_list(input_list).expect("Failed to read the input list");
+		for path in listed_files {
+			if seen_files.insert(path.canonicalize().unwrap_or_else(|_| path.clone())) {
+				image_files.push(Ok(path));
+			}
+		}
+	}
+	let mut files_rng = Rng::from_seed_u64(seed);
+	sort_files(&mut image_files, &opt.sort, &mut files_rng);
+	if opt.sample > 0 {
+		image_files = sample_files(image_files, opt.sample as usize, &mut files_rng);
+	}
+	timings.add_duration(Stage::Globbing, globbing_start.elapsed());
+
+	// Blending modes can be picked from groups of modes, so they're all put in a single list
+	let blending_modes: Vec<WeightedValue<BlendingMode>> = opt.blending_mode.concat();
+	let mut blending_modes_deck = match opt.blend_order {
+		BlendOrder::WeightedRandom => None,
+		BlendOrder::Cycle => Some(Deck::new(&blending_modes, None)),
+		// The files are already sorted, so their generator can be used for the deck
+		BlendOrder::Shuffle => Some(Deck::new(&blending_modes, Some(files_rng))),
+	};
+
+	// Show progress as a bar, or file by file when verbose
+	let progress_bar = ProgressBar::new(image_files.len());
+	if log_level == LogLevel::Normal {
+		progress_bar.draw(0);
+	}
+
+	// Images are read, detected, and aligned in parallel, a few at a time; they're then blended one by one, in
+	// order, so results are still deterministic
+	let chunk_size = rayon::current_num_threads() * 2;
+	'files: for image_files_chunk in image_files.chunks(chunk_size) {
+		let processed_images = image_files_chunk
+			.par_iter()
+			.map(|image_file| {
+				image_file.as_ref().ok().map(|path| {
+					process_image(
+						path,
+						face_detector.as_ref(),
+						&opt,
+						target_faces_rect,
+						target_eye_distance,
+						seed,
+					)
+				})
+			})
+			.collect::<Vec<Option<Vec<ProcessedImage>>>>();
+
+		for (image_file, processed_images) in image_files_chunk.iter().zip(processed_images) {
+			if let (Ok(path), Some(processed_images)) = (image_file, processed_images) {
+				// Each frame used from an animated file counts as an image of its own
+				for mut processed_image in processed_images {
+					// File can be opened
+					timings.add(&processed_image.timings);
+
+					// Skip images with faces that look like one read before, if deduplicating
+					let duplicate_of = processed_image
+						.hash
+						.filter(|_| processed_image.num_faces_selected > 0)
+						.and_then(|hash| {
+							seen_hashes
+								.iter()
+								.find(|(seen_hash, _)| {
+									hamming_distance(hash, *seen_hash) <= opt.dedupe_distance
+								})
+								.map(|(_, seen_path)| seen_path)
+						});
+					if let Some(duplicate_of) = duplicate_of {
+						processed_image.log.push_str(&format!(
+							"; duplicate of {:?}, skipping.",
+							duplicate_of.file_name().unwrap()
+						));
+						processed_image.faces.clear();
+						processed_image.num_faces_selected = 0;
+						num_files_duplicate += 1;
+					} else if let Some(hash) = processed_image.hash {
+						seen_hashes.push((hash, path.clone()));
+					}
+					if opt.metadata.is_some() {
+						metadata_files.push(path.clone());
+					}
+					if log_level >= LogLevel::Verbose {
+						terminal::erase_line_to_end();
+						print!(
+							"({}/{}) Reading {:?}{}",
+							num_files_read + 1,
+							image_files.len(),
+							&path.file_name().unwrap(),
+							processed_image.log
+						);
+						if processed_image.faces.is_empty() {
+							println!();
+						}
+					}
+
+					if processed_image.is_valid {
+						num_files_by_faces[processed_image.num_faces_detected.min(2)] += 1;
+						num_faces_detected += processed_image.num_faces_detected;
+						num_faces_accepted += processed_image.num_faces_accepted;
+						if processed_image.num_faces_selected > 0 {
+							num_files_usable += 1;
+						}
+					} else {
+						num_files_invalid += 1;
+					}
 
-		num_images_read += 1;
+					let mut rng = processed_image.rng;
 
-		if opt.max_images > 0 && num_images_used >= opt.max_images as usize {
-			terminal::erase_line_to_end();
-			println!("Reached the maximum number of input images; skipping additional files.");
-			break;
+					for (face_index, aligned_face) in processed_image.faces.iter().enumerate() {
+						if opt.max_images > 0 && num_images_used >= opt.max_images as usize {
+							break;
+						}
+
+						if log_level >= LogLevel::Verbose {
+							if face_index > 0 {
+								// Each additional face from the same image gets its own line
+								terminal::erase_line_to_end();
+								print!(
+									"({}/{}) Face {}/{} of {:?}",
+									num_files_read + 1,
+									image_files.len(),
+									face_index + 1,
+									processed_image.faces.len(),
+									&path.file_name().unwrap()
+								);
+							}
+							println!("{}", aligned_face.log);
+						}
+
+						// Get all the options
+						let param_opacity: [f32; 3] = if opt.opacity_rgb.is_empty() {
+							[get_random_range_weighted(&mut rng, &opt.opacity) as f32; 3]
+						} else {
+							get_random_range_triple_weighted(&mut rng, &opt.opacity_rgb).map(|v| v as f32)
+						};
+						let param_opacity = if opt.confidence_weighting {
+							param_opacity.map(|v| (v * aligned_face.confidence).clamp(0.0, 1.0))
+						} else {
+							param_opacity
+						};
+						let param_opacity =
+							param_opacity.map(|v| opt.opacity_decay.apply(v, num_images_used));
+						let param_crop_rect = {
+							let crop_width = get_random_size_range_weighted(
+								&mut rng,
+								&opt.crop_width,
+								target_width,
+								aligned_face.size.0,
+							);
+							let crop_height = get_random_size_range_weighted(
+								&mut rng,
+								&opt.crop_height,
+								target_height,
+								aligned_face.size.1,
+							);
+							opt.crop_anchor.place_crop(
+								&mut rng,
+								(target_width, target_height),
+								(crop_width, crop_height),
+								(opt.anchor_x, opt.anchor_y),
+							)
+						};
+						let param_mask = Mask {
+							rect: param_crop_rect,
+							shape: opt.mask_shape.clone(),
+							feather: opt.feather.to_pixels_for_face(
+								param_crop_rect.2.min(param_crop_rect.3),
+								aligned_face.size.0.min(aligned_face.size.1),
+							) as f32,
+							vignette: opt.vignette.as_ref().map(|(radius, strength)| Vignette {
+								center: (
+									target_width as f32 * opt.anchor_x,
+									target_height as f32 * opt.anchor_y,
+								),
+								radius: radius.to_pixels_for_face(
+									target_width.min(target_height),
+									aligned_face.size.0.min(aligned_face.size.1),
+								) as f32,
+								strength: *strength as f32,
+							}),
+						};
+						let param_blending_mode = match &mut blending_modes_deck {
+							Some(deck) => &blending_modes[deck.draw()].value,
+							None => get_random_entry_weighted(&mut rng, &blending_modes),
+						};
+
+						// Mirror the layer, if picked; the anchor is mirrored too, so the face stays in place
+						let param_flip = opt.flip_chance > 0.0 && rng.next_f64() < opt.flip_chance as f64;
+						let flipped_image: RgbaImage;
+						let (layer_image, layer_anchor) = if param_flip {
+							flipped_image = imageops::flip_horizontal(&aligned_face.image);
+							let anchor =
+								(flipped_image.width() as f32 - aligned_face.anchor.0, aligned_face.anchor.1);
+							(&flipped_image, anchor)
+						} else {
+							(&aligned_face.image, aligned_face.anchor)
+						};
+
+						// Rotate the layer around its anchor, if picked
+						let param_rotation = if opt.rotation.is_empty() {
+							0.0
+						} else {
+							get_random_range_weighted(&mut rng, &opt.rotation) as f32
+						};
+						let rotated_image: RgbaImage;
+						let (layer_image, layer_anchor) = if param_rotation == 0.0 {
+							(layer_image, layer_anchor)
+						} else {
+							let anchor;
+							(rotated_image, anchor) =
+								rotate_image(layer_image, param_rotation.to_radians(), layer_anchor);
+							(&rotated_image, anchor)
+						};
+
+						// Invert the layer, if picked
+						let param_invert = opt.invert_layers
+							|| (opt.invert_chance > 0.0 && rng.next_f64() < opt.invert_chance as f64);
+						let inverted_image: RgbaImage;
+						let layer_image = if param_invert {
+							let mut image = layer_image.clone();
+							imageops::invert(&mut image);
+							inverted_image = image;
+							&inverted_image
+						} else {
+							layer_image
+						};
+
+						// Desaturate the layer, if requested
+						let desaturated_image: RgbaImage;
+						let layer_image = if opt.desaturate > 0.0 {
+							let mut image = layer_image.clone();
+							desaturate(&mut image, opt.desaturate, &opt.luma);
+							desaturated_image = image;
+							&desaturated_image
+						} else {
+							layer_image
+						};
+
+						// Tint the layer, if picked
+						let param_tint = if opt.tint.is_empty() {
+							None
+						} else {
+							Some(*get_random_entry_weighted(&mut rng, &opt.tint))
+						};
+						let tinted_image: RgbaImage;
+						let layer_image = if let Some((color, strength)) = param_tint {
+							let mut image = layer_image.clone();
+							tint(&mut image, color, strength, &opt.luma);
+							tinted_image = image;
+							&tinted_image
+						} else {
+							layer_image
+						};
+
+						let param_offset: XYi = xyf_to_xyi((
+							target_width as f32 * opt.anchor_x - layer_anchor.0,
+							target_height as f32 * opt.anchor_y - layer_anchor.1,
+						));
+
+						// Layers entirely outside the output (e.g. after extreme scaling) can't be painted
+						let layer_rect: XYWHi =
+							(param_offset.0, param_offset.1, layer_image.width(), layer_image.height());
+						if intersect((0, 0, target_width, target_height), layer_rect).is_none() {
+							if opt.strict {
+								panic!(
+									"Cannot blend image; no intersection between the output and the layer."
+								);
+							}
+							if log_level >= LogLevel::Normal {
+								terminal::erase_line_to_end();
+								println!(
+									"Warning: skipping layer, since it falls entirely outside the output image."
+								);
+							}
+							num_layers_skipped += 1;
+							continue;
+						}
+
+						// Finally, blend it all (or keep it for later)
+						timings.time(Stage::Blend, || {
+							if let Some(stack) = &mut stack {
+								stack_image(stack, layer_image, param_offset, Some(&param_mask));
+							} else {
+								blend_image(
+									&mut output_image,
+									layer_image,
+									param_offset,
+									param_opacity,
+									param_blending_mode,
+									&opt.luma,
+									Some(&param_mask),
+									opt.linear_light,
+								);
+							}
+						});
+
+						if let Some(contact_sheet) = &mut contact_sheet {
+							contact_sheet.add(layer_image, param_offset);
+						}
+						if opt.metadata.is_some() {
+							metadata_layers.push(LayerMetadata {
+								path: path.clone(),
+								face_rect: aligned_face.rect,
+								confidence: aligned_face.confidence,
+								opacity: param_opacity,
+								crop_rect: param_crop_rect,
+								blending_mode: param_blending_mode.clone(),
+								flip: param_flip,
+								rotation: param_rotation,
+								invert: param_invert,
+								tint: param_tint,
+								offset: param_offset,
+							});
+						}
+
+						num_images_used += 1;
+
+						// Save the output image so far, if making frames
+						if (opt.frames_dir.is_some() || animation.is_some())
+							&& num_images_used.is_multiple_of(opt.frames_every as usize)
+						{
+							let frame = match &stack {
+								Some(stack) => &stack.to_image(&output_image),
+								None => &output_image,
+							};
+							if let Some(frames_dir) = &opt.frames_dir {
+								encode_image(frame, opt.linear_light, false, is_transparent, opt.dither)
+									.save_with_format(
+										frames_dir.join(format!("frame_{:05}.png", num_images_used)),
+										ImageFormat::Png,
+									)
+									.expect("Failed to save frame image");
+							}
+							if let Some(animation) = &mut animation {
+								// Each frame of an animation is adjusted like the final image, so they all look alike
+								let mut frame = frame.clone();
+								adjust_colors(&mut frame, &opt);
+								animation.add_frame(
+									encode_image(&frame, opt.linear_light, false, is_transparent, opt.dither)
+										.into_rgba8(),
+								);
+							}
+						}
+
+						if log_level >= LogLevel::Verbose {
+							terminal::cursor_up();
+						}
+					}
+
+					num_images_read += 1;
+					if opt.max_images > 0 && num_images_used >= opt.max_images as usize {
+						if log_level >= LogLevel::Normal {
+							terminal::erase_line_to_end();
+							println!(
+								"Reached the maximum number of input images; skipping additional files."
+							);
+						}
+						break 'files;
+					}
+				}
+			}
+
+			num_files_read += 1;
+			if log_level == LogLevel::Normal {
+				progress_bar.draw(num_files_read);
+			}
 		}
 	}
 
 	terminal::erase_line_to_end();
-	println!("Done. {} images processed, with {} valid images used.", image_files.len(), num_images_used);
+	if opt.dry_run {
+		println!("Done. {} images processed in a dry run.", num_images_read);
+		println!("Images with no faces: {}", num_files_by_faces[0]);
+		println!("Images with 1 face: {}", num_files_by_faces[1]);
+		println!("Images with 2 or more faces: {}", num_files_by_faces[2]);
+		println!("Invalid files: {}", num_files_invalid);
+		if opt.dedupe {
+			println!("Duplicate images: {}", num_files_duplicate);
+		}
+		println!(
+			"Faces passing the minimum confidence, size, and edge margin: {} of {}",
+			num_faces_accepted, num_faces_detected
+		);
+		println!("Images with faces that would be used: {}", num_files_usable);
+		if opt.timings {
+			timings.print(start_time);
+		}
+		return;
+	}
+	println!(
+		"Done. {} images processed, with {} valid images used, using seed {}.",
+		num_images_read, num_images_used, seed
+	);
+	if num_layers_skipped > 0 {
+		println!("{} layers were skipped for falling outside the output image.", num_layers_skipped);
+	}
+	if num_files_duplicate > 0 {
+		println!("{} images were skipped for being duplicates of others.", num_files_duplicate);
+	}
+
+	// Combine all the stacked layers, if stacking
+	if let Some(stack) = &stack {
+		output_image = stack.to_image(&output_image);
+	}
 
-	// Convert the output image from Rgb-32f to Rgb-u8
-	let mut output_u8 = RgbImage::new(output_image.width(), output_image.height());
-	{
-		for (x, y, pixel) in output_image.enumerate_pixels() {
-			let scaled = pixel.0.map(|v| (v * 255.0).round().clamp(0.0, 255.0) as u8);
-			output_u8.put_pixel(x, y, Rgb(scaled));
+	// Dump the combined values before they're adjusted or encoded, if debugging
+	if let Some(debug_accumulator_path) = &opt.debug_accumulator {
+		output_image
+			.save_with_format(debug_accumulator_path, ImageFormat::OpenExr)
+			.expect("Could not save the accumulator image");
+	}
+
+	// Adjust the colors of the final image, if requested
+	adjust_colors(&mut output_image, &opt);
+
+	// Finally, save the final image at each size; it only keeps the alpha channel if the background is
+	// transparent. An animation only needs its last frame, if it wasn't added already
+	for (size, output_path) in &output_paths {
+		if *size != (target_width, target_height) {
+			save_output(&resize_to_cover(&output_image, *size), output_path, &opt, is_transparent);
+		} else if let Some(mut animation) = animation.take() {
+			if num_images_used == 0 || !num_images_used.is_multiple_of(opt.frames_every as usize) {
+				animation.add_frame(
+					encode_image(&output_image, opt.linear_light, false, is_transparent, opt.dither)
+						.into_rgba8(),
+				);
+			}
+		} else {
+			save_output(&output_image, output_path, &opt, is_transparent);
 		}
 	}
 
-	// Finally, saved the final image
-	output_u8.save(&opt.output).expect("Failed to save output image");
+	if let (Some(contact_sheet_path), Some(contact_sheet)) = (&opt.contact_sheet, &contact_sheet) {
+		contact_sheet.to_image().save(contact_sheet_path).expect("Could not save the contact sheet");
+	}
+
+	// Write down how the image was made, if requested
+	if let Some(metadata_path) = &opt.metadata {
+		let metadata = Metadata {
+			seed,
+			files: metadata_files,
+			layers: metadata_layers,
+		};
+		save_metadata(metadata_path, &metadata).expect("Could not write the metadata file");
+	}
+
+	if opt.timings {
+		timings.print(start_time);
+	}
 }
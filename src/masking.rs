@@ -0,0 +1,154 @@
+use strum_macros::{Display, EnumString};
+
+use crate::{
+	geom::{WHi, XYWHi, XYf, XYi, distance, xyf_to_xyi},
+	rng::Rng,
+};
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum MaskShape {
+	#[strum(serialize = "rect")]
+	Rect,
+	#[strum(serialize = "ellipse")]
+	Ellipse,
+}
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum CropAnchor {
+	#[strum(serialize = "random")]
+	Random,
+	#[strum(serialize = "face")]
+	Face,
+}
+
+impl CropAnchor {
+	/// Places a crop in the output, from its size in pixels and the anchor (0-1) of the output. Crops can't be
+	/// larger than the output; when covering a whole axis, they can't move on it. Crops centered on the anchor
+	/// can go past the edges
+	pub fn place_crop(&self, rng: &mut Rng, output_size: WHi, crop_size: (f64, f64), anchor: XYf) -> XYWHi {
+		let crop_width = (crop_size.0.round() as u32).min(output_size.0);
+		let crop_height = (crop_size.1.round() as u32).min(output_size.1);
+		let crop_position: XYi = match self {
+			Self::Random => (
+				rng.next_u32_range_inclusive(0, output_size.0 - crop_width) as i32,
+				rng.next_u32_range_inclusive(0, output_size.1 - crop_height) as i32,
+			),
+			Self::Face => xyf_to_xyi((
+				output_size.0 as f32 * anchor.0 - crop_width as f32 / 2.0,
+				output_size.1 as f32 * anchor.1 - crop_height as f32 / 2.0,
+			)),
+		};
+		(crop_position.0, crop_position.1, crop_width, crop_height)
+	}
+}
+
+/// Radial falloff of the opacity around a point, reaching zero at a radius
+pub struct Vignette {
+	pub center: XYf,
+	pub radius: f32,
+	/// Exponent of the falloff; higher values fade out closer to the center
+	pub strength: f32,
+}
+
+impl Vignette {
+	#[inline(always)]
+	fn opacity_at(&self, x: i32, y: i32) -> f32 {
+		let relative_distance = distance((x as f32 + 0.5, y as f32 + 0.5), self.center) / self.radius;
+		if self.radius <= 0.0 || relative_distance >= 1.0 {
+			0.0
+		} else {
+			(1.0 - relative_distance * relative_distance).powf(self.strength)
+		}
+	}
+}
+
+/// Area of the output a layer is painted on, with optional soft edges
+pub struct Mask {
+	pub rect: XYWHi,
+	pub shape: MaskShape,
+	/// Distance from the edges, in pixels, over which the opacity fades in
+	pub feather: f32,
+	pub vignette: Option<Vignette>,
+}
+
+impl Mask {
+	/// The opacity multiplier (0-1) for a pixel in the output
+	#[inline(always)]
+	pub fn opacity_at(&self, x: i32, y: i32) -> f32 {
+		match &self.vignette {
+			Some(vignette) => self.shape_opacity_at(x, y) * vignette.opacity_at(x, y),
+			None => self.shape_opacity_at(x, y),
+		}
+	}
+
+	#[inline(always)]
+	fn shape_opacity_at(&self, x: i32, y: i32) -> f32 {
+		if self.shape == MaskShape::Rect && self.feather <= 0.0 {
+			return 1.0;
+		}
+
+		// Distance from the pixel center to the closest edge
+		let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+		let (x1, y1) = (self.rect.0 as f32, self.rect.1 as f32);
+		let (width, height) = (self.rect.2 as f32, self.rect.3 as f32);
+		let edge_distance = match self.shape {
+			MaskShape::Rect => (x - x1).min(x1 + width - x).min(y - y1).min(y1 + height - y),
+			MaskShape::Ellipse => {
+				// Approximated from the distance to the center, relative to the radius in that direction
+				let (radius_x, radius_y) = (width / 2.0, height / 2.0);
+				let relative_distance = (((x - x1 - radius_x) / radius_x).powi(2)
+					+ ((y - y1 - radius_y) / radius_y).powi(2))
+				.sqrt();
+				(1.0 - relative_distance) * radius_x.min(radius_y)
+			}
+		};
+		if edge_distance <= 0.0 {
+			0.0
+		} else if self.feather <= 0.0 {
+			1.0
+		} else {
+			(edge_distance / self.feather).min(1.0)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn random_crop_covering_whole_width() {
+		let mut rng = Rng::from_seed(1);
+		for _ in 0..100 {
+			let (x, y, width, height) =
+				CropAnchor::Random.place_crop(&mut rng, (200, 100), (200.0, 50.0), (0.5, 0.5));
+			assert_eq!((x, width, height), (0, 200, 50));
+			assert!((0..=50).contains(&y));
+		}
+	}
+
+	#[test]
+	fn random_crop_covering_whole_height() {
+		let mut rng = Rng::from_seed(1);
+		for _ in 0..100 {
+			let (x, y, width, height) =
+				CropAnchor::Random.place_crop(&mut rng, (200, 100), (50.0, 100.0), (0.5, 0.5));
+			assert_eq!((y, width, height), (0, 50, 100));
+			assert!((0..=150).contains(&x));
+		}
+	}
+
+	#[test]
+	fn random_crop_covering_whole_output() {
+		let mut rng = Rng::from_seed(1);
+		assert_eq!(
+			CropAnchor::Random.place_crop(&mut rng, (200, 100), (200.0, 100.0), (0.5, 0.5)),
+			(0, 0, 200, 100)
+		);
+		// Crops larger than the output are shrunk to it
+		assert_eq!(
+			CropAnchor::Random.place_crop(&mut rng, (200, 100), (250.0, 400.0), (0.5, 0.5)),
+			(0, 0, 200, 100)
+		);
+	}
+}
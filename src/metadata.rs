@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::blending::BlendingMode;
+use crate::geom::{XYWHf, XYWHi, XYi};
+
+/// The settings picked for a layer painted on the output image. Rectangles are saved as [x, y, width, height]
+#[derive(Serialize)]
+pub struct LayerMetadata {
+	#[serde(rename = "file")]
+	pub path: PathBuf,
+	/// Rectangle of the face in the input image, as detected
+	#[serde(rename = "face")]
+	pub face_rect: XYWHf,
+	pub confidence: f32,
+	pub opacity: [f32; 3],
+	#[serde(rename = "crop")]
+	pub crop_rect: XYWHi,
+	pub blending_mode: BlendingMode,
+	pub flip: bool,
+	/// Rotation, in degrees
+	pub rotation: f32,
+	pub invert: bool,
+	/// Color and strength of the tint, if any
+	pub tint: Option<([f32; 3], f32)>,
+	/// Position of the layer's top left corner in the output image
+	pub offset: XYi,
+}
+
+/// Everything needed to audit (or reproduce) how an output image was made
+#[derive(Serialize)]
+pub struct Metadata {
+	pub seed: u64,
+	/// All the input files that were read, whether they had faces or not
+	pub files: Vec<PathBuf>,
+	pub layers: Vec<LayerMetadata>,
+}
+
+pub fn save_metadata(path: &Path, metadata: &Metadata) -> io::Result<()> {
+	let mut writer = BufWriter::new(File::create(path)?);
+	serde_json::to_writer_pretty(&mut writer, metadata)?;
+	writeln!(writer)?;
+	writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+	use strum::IntoEnumIterator;
+
+	use super::*;
+
+	#[test]
+	fn blending_modes_use_option_names() {
+		for mode in BlendingMode::iter() {
+			assert_eq!(serde_json::to_value(&mode).unwrap(), serde_json::Value::String(mode.to_string()));
+		}
+	}
+
+	#[test]
+	fn json_is_valid() {
+		let metadata = Metadata {
+			seed: 1,
+			files: vec![PathBuf::from("a \"quoted\"\\path\n.jpg")],
+			layers: vec![LayerMetadata {
+				path: PathBuf::from("a \"quoted\"\\path\n.jpg"),
+				face_rect: (1.0, 2.0, 3.0, 4.0),
+				confidence: f32::NAN,
+				opacity: [f32::INFINITY, 0.5, 0.5],
+				crop_rect: (0, 0, 10, 10),
+				blending_mode: BlendingMode::ColorDodge,
+				flip: false,
+				rotation: 0.0,
+				invert: false,
+				tint: None,
+				offset: (-5, 5),
+			}],
+		};
+		let json = serde_json::to_string_pretty(&metadata).unwrap();
+		let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+		assert_eq!(value["files"][0], "a \"quoted\"\\path\n.jpg");
+		assert_eq!(value["layers"][0]["confidence"], serde_json::Value::Null);
+		assert_eq!(value["layers"][0]["blending_mode"], "color-dodge");
+		assert_eq!(value["layers"][0]["crop"], serde_json::json!([0, 0, 10, 10]));
+	}
+}
@@ -1,21 +1,105 @@
 // Originally (partly) from https://github.com/zeh/random-art-generator/blob/main/src/generator/utils/parsing.rs
 use std::str::FromStr;
 
+use image::ImageFormat;
+
 use crate::{
-	blending::BlendingMode,
+	blending::{BlendingMode, OpacityDecay},
 	units::{SizeUnit, WeightedValue},
 };
 
-fn parse_integer(src: &str) -> Result<u32, &str> {
+fn parse_integer(src: &str) -> Result<u32, &'static str> {
 	src.parse::<u32>().or(Err("Could not parse integer value"))
 }
 
-fn parse_integer_list(src: &str, divider: char) -> Result<Vec<u32>, &str> {
+/// Parses an integer that must be higher than 0
+pub fn parse_positive_integer(src: &str) -> Result<u32, &'static str> {
+	match parse_integer(src)? {
+		0 => Err("Value must be higher than 0"),
+		value => Ok(value),
+	}
+}
+
+/// Parses a bit depth for the output image; only 8 and 16 bits per channel are supported
+pub fn parse_bit_depth(src: &str) -> Result<u32, &'static str> {
+	match parse_integer(src)? {
+		8 => Ok(8),
+		16 => Ok(16),
+		_ => Err("Bit depth must be 8 or 16"),
+	}
+}
+
+/// Parses the quality for lossy formats (JPEG and WebP), from 1 to 100
+pub fn parse_quality(src: &str) -> Result<u8, &'static str> {
+	match parse_integer(src)? {
+		value @ 1..=100 => Ok(value as u8),
+		_ => Err("Quality must be between 1 and 100"),
+	}
+}
+
+/// Parses an image format from its usual file extension (e.g. "png", "jpg", "exr")
+pub fn parse_image_format(src: &str) -> Result<ImageFormat, &'static str> {
+	ImageFormat::from_extension(src.to_lowercase()).ok_or("Unknown image format")
+}
+
+/// Parses a scale that can only make things smaller, higher than 0 and up to 1
+pub fn parse_downscale(src: &str) -> Result<f64, &'static str> {
+	match parse_float(src)? {
+		value if value > 0.0 && value <= 1.0 => Ok(value),
+		_ => Err("Scale must be higher than 0, and up to 1"),
+	}
+}
+
+/// Parses a threshold, from 0 to 1 (e.g. "0.5")
+pub fn parse_threshold(src: &str) -> Result<f32, &'static str> {
+	match parse_float(src)? {
+		value if (0.0..=1.0).contains(&value) => Ok(value as f32),
+		_ => Err("Threshold must be between 0 and 1"),
+	}
+}
+
+/// Parses an amount, from 0 (none) to 1 (all)
+pub fn parse_amount(src: &str) -> Result<f32, &'static str> {
+	match parse_float(src)? {
+		value if (0.0..=1.0).contains(&value) => Ok(value as f32),
+		_ => Err("Amount must be between 0 and 1"),
+	}
+}
+
+/// Parses an anchor position, as a fraction of the output size from 0 to 1 (e.g. "0.4")
+pub fn parse_anchor(src: &str) -> Result<f32, &'static str> {
+	match parse_float(src)? {
+		value if (0.0..=1.0).contains(&value) => Ok(value as f32),
+		_ => Err("Anchor must be between 0 and 1"),
+	}
+}
+
+/// Parses an opacity decay: "none", "average", or a factor from 0 to 1 (e.g. "0.95")
+pub fn parse_opacity_decay(src: &str) -> Result<OpacityDecay, &'static str> {
+	match src.to_lowercase().as_str() {
+		"none" => Ok(OpacityDecay::None),
+		"average" => Ok(OpacityDecay::Average),
+		_ => match parse_float(src) {
+			Ok(value) if (0.0..=1.0).contains(&value) => Ok(OpacityDecay::Factor(value)),
+			_ => Err("Opacity decay must be \"none\", \"average\", or a factor between 0 and 1"),
+		},
+	}
+}
+
+/// Parses a percentage, from 0 to 100 (e.g. "0.5", "10", with or without a "%")
+pub fn parse_percentage(src: &str) -> Result<f32, &'static str> {
+	match parse_float(src.strip_suffix('%').unwrap_or(src))? {
+		value if (0.0..=100.0).contains(&value) => Ok(value as f32),
+		_ => Err("Percentage must be between 0 and 100"),
+	}
+}
+
+fn parse_integer_list(src: &str, divider: char) -> Result<Vec<u32>, &'static str> {
 	src.split(divider).collect::<Vec<&str>>().iter().map(|&e| parse_integer(e)).collect()
 }
 
 /// Parses a dimensions string (999x999) into a (u32, u32) width/height tuple.
-pub fn parse_image_dimensions(src: &str) -> Result<(u32, u32), &str> {
+pub fn parse_image_dimensions(src: &str) -> Result<(u32, u32), &'static str> {
 	let values = parse_integer_list(&src, 'x')?;
 	match values.len() {
 		2 => Ok((values[0], values[1])),
@@ -23,16 +107,16 @@ pub fn parse_image_dimensions(src: &str) -> Result<(u32, u32), &str> {
 	}
 }
 
-fn parse_float(src: &str) -> Result<f64, &str> {
+fn parse_float(src: &str) -> Result<f64, &'static str> {
 	src.parse::<f64>().or(Err("Could not parse float value"))
 }
 
-fn parse_float_list(src: &str, divider: char) -> Result<Vec<f64>, &str> {
+fn parse_float_list(src: &str, divider: char) -> Result<Vec<f64>, &'static str> {
 	src.split(divider).collect::<Vec<&str>>().iter().map(|&e| parse_float(e)).collect()
 }
 
 /// Parses "1.0", "0.9-1.0" into (1.0, 1.0), (0.9, 1.0)
-fn parse_float_pair(src: &str) -> Result<(f64, f64), &str> {
+fn parse_float_pair(src: &str) -> Result<(f64, f64), &'static str> {
 	let values = parse_float_list(&src, '-')?;
 	match values.len() {
 		1 => Ok((values[0], values[0])),
@@ -41,7 +125,71 @@ fn parse_float_pair(src: &str) -> Result<(f64, f64), &str> {
 	}
 }
 
-fn parse_size(src: &str) -> Result<SizeUnit, &str> {
+/// Parses "1.0", "-5", "-5-5", "-10--5" into (1.0, 1.0), (-5.0, -5.0), (-5.0, 5.0), (-10.0, -5.0)
+fn parse_signed_float_pair(src: &str) -> Result<(f64, f64), &'static str> {
+	if let Ok(value) = src.parse::<f64>() {
+		return Ok((value, value));
+	}
+	// The divider is the first "-" that follows a number, rather than being a sign
+	let divider = src
+		.char_indices()
+		.skip(1)
+		.find(|&(index, c)| c == '-' && src[..index].ends_with(|p: char| p.is_ascii_digit() || p == '.'))
+		.map(|(index, _)| index);
+	match divider {
+		Some(index) => Ok((parse_float(&src[..index])?, parse_float(&src[index + 1..])?)),
+		None => Err("Could not parse float range"),
+	}
+}
+
+/// Parses "1.0/0.5/0.5", "0.9-1.0/0.5/0.5-0.6" into three (f64, f64) pairs, one per RGB channel
+fn parse_float_pair_triple(src: &str) -> Result<[(f64, f64); 3], &'static str> {
+	let values = src.split('/').map(parse_float_pair).collect::<Result<Vec<(f64, f64)>, &'static str>>()?;
+	match values.len() {
+		3 => Ok([values[0], values[1], values[2]]),
+		_ => Err("Channel values should use RED/GREEN/BLUE"),
+	}
+}
+
+fn parse_hex_channel(src: &str) -> Result<f32, &'static str> {
+	match u8::from_str_radix(src, 16) {
+		Ok(value) => Ok(value as f32 / 255.0),
+		_ => Err("Could not parse hex color value"),
+	}
+}
+
+/// Parses a color name ("black", "white", "gray") or hex value ("#fff", "#202020") into 0-1 RGB values
+pub fn parse_color(src: &str) -> Result<[f32; 3], &'static str> {
+	match src.to_lowercase().as_str() {
+		"black" => Ok([0.0, 0.0, 0.0]),
+		"white" => Ok([1.0, 1.0, 1.0]),
+		"gray" | "grey" => Ok([0.5, 0.5, 0.5]),
+		_ => match src.strip_prefix('#') {
+			Some(hex) if hex.is_ascii() && hex.len() == 3 => Ok([
+				parse_hex_channel(&hex[0..1].repeat(2))?,
+				parse_hex_channel(&hex[1..2].repeat(2))?,
+				parse_hex_channel(&hex[2..3].repeat(2))?,
+			]),
+			Some(hex) if hex.is_ascii() && hex.len() == 6 => Ok([
+				parse_hex_channel(&hex[0..2])?,
+				parse_hex_channel(&hex[2..4])?,
+				parse_hex_channel(&hex[4..6])?,
+			]),
+			_ => Err("Colors should be a name, or use #RGB or #RRGGBB"),
+		},
+	}
+}
+
+/// Parses a background color, which can be any color or "transparent", into 0-1 RGBA values
+pub fn parse_background(src: &str) -> Result<[f32; 4], &'static str> {
+	match src.to_lowercase().as_str() {
+		"transparent" => Ok([0.0, 0.0, 0.0, 0.0]),
+		_ => parse_color(src).map(|color| [color[0], color[1], color[2], 1.0]),
+	}
+}
+
+/// Parses a size in pixels ("10") or as a percentage ("10%")
+pub fn parse_size(src: &str) -> Result<SizeUnit, &'static str> {
 	if src.ends_with("%") {
 		match src[..src.len() - 1].parse::<f64>() {
 			Ok(value) => Ok(SizeUnit::Fraction(value / 100.0f64)),
@@ -55,12 +203,43 @@ fn parse_size(src: &str) -> Result<SizeUnit, &str> {
 	}
 }
 
-fn parse_size_list(src: &str, divider: char) -> Result<Vec<SizeUnit>, &str> {
-	src.split(divider).collect::<Vec<&str>>().iter().map(|&e| parse_size(e)).collect()
+/// Parses a size in pixels ("10"), as a percentage ("10%"), or as a multiple of the face size ("2f")
+pub fn parse_face_size(src: &str) -> Result<SizeUnit, &'static str> {
+	match src.strip_suffix('f') {
+		Some(value) => match value.parse::<f64>() {
+			Ok(value) => Ok(SizeUnit::FaceRelative(value)),
+			_ => Err("Could not parse face multiple value"),
+		},
+		None => parse_size(src),
+	}
+}
+
+/// Parses a vignette radius with an optional strength (e.g. "40%", "300/2", "1.5f") into a (SizeUnit, f64) tuple
+pub fn parse_vignette(src: &str) -> Result<(SizeUnit, f64), &'static str> {
+	let values = src.split('/').collect::<Vec<&str>>();
+	match values.len() {
+		1 => Ok((parse_face_size(values[0])?, 1.0)),
+		2 => Ok((parse_face_size(values[0])?, parse_float(values[1])?)),
+		_ => Err("Vignette must be RADIUS or RADIUS/STRENGTH"),
+	}
+}
+
+/// Parses a color with an optional strength from 0 to 1 (e.g. "#f80", "#704214/0.5") into a ([f32; 3], f32) tuple
+fn parse_tint(src: &str) -> Result<([f32; 3], f32), &'static str> {
+	let values = src.split('/').collect::<Vec<&str>>();
+	match values.len() {
+		1 => Ok((parse_color(values[0])?, 1.0)),
+		2 => Ok((parse_color(values[0])?, parse_amount(values[1])?)),
+		_ => Err("Tint must be COLOR or COLOR/STRENGTH"),
+	}
+}
+
+fn parse_size_list(src: &str, divider: char) -> Result<Vec<SizeUnit>, &'static str> {
+	src.split(divider).collect::<Vec<&str>>().iter().map(|&e| parse_face_size(e)).collect()
 }
 
-// Parses "100%", "90%-100%", "10-20", "2" into pairs of SizeUnits
-fn parse_size_pair(src: &str) -> Result<(SizeUnit, SizeUnit), &str> {
+// Parses "100%", "90%-100%", "10-20", "2", "1f-2f" into pairs of SizeUnits
+fn parse_size_pair(src: &str) -> Result<(SizeUnit, SizeUnit), &'static str> {
 	let values = parse_size_list(&src, '-')?;
 	match values.len() {
 		1 => Ok((values[0].clone(), values[0].clone())),
@@ -70,7 +249,7 @@ fn parse_size_pair(src: &str) -> Result<(SizeUnit, SizeUnit), &str> {
 }
 
 /// Parses "*@n" into a string "*" with n weight. This is used so we can have pairs with weights.
-fn parse_weight(src: &str) -> Result<(&str, f64), &str> {
+fn parse_weight(src: &str) -> Result<(&str, f64), &'static str> {
 	let values = src.split('@').collect::<Vec<&str>>();
 	match values.len() {
 		1 => Ok((src, 1.0)),
@@ -82,8 +261,8 @@ fn parse_weight(src: &str) -> Result<(&str, f64), &str> {
 	}
 }
 
-/// Parses a size pair with a weight (e.f. "1-2@1", "10%", "5-10%@2") into a WeightedValue<>
-pub fn parse_weighted_size_pair(src: &str) -> Result<WeightedValue<(SizeUnit, SizeUnit)>, &str> {
+/// Parses a size pair with a weight (e.f. "1-2@1", "10%", "5-10%@2", "1.5f-2f") into a WeightedValue<>
+pub fn parse_weighted_size_pair(src: &str) -> Result<WeightedValue<(SizeUnit, SizeUnit)>, &'static str> {
 	match parse_weight(src) {
 		Ok((src_value, weight)) => match parse_size_pair(src_value) {
 			Ok(value) => Ok(WeightedValue {
@@ -97,7 +276,7 @@ pub fn parse_weighted_size_pair(src: &str) -> Result<WeightedValue<(SizeUnit, Si
 }
 
 /// Parses a float pair with a weight (e.f. "1-2@1", "10.2", "5.2-10@2") into a WeightedValue<>
-pub fn parse_weighted_float_pair(src: &str) -> Result<WeightedValue<(f64, f64)>, &str> {
+pub fn parse_weighted_float_pair(src: &str) -> Result<WeightedValue<(f64, f64)>, &'static str> {
 	match parse_weight(src) {
 		Ok((src_value, weight)) => match parse_float_pair(src_value) {
 			Ok(value) => Ok(WeightedValue {
@@ -110,8 +289,50 @@ pub fn parse_weighted_float_pair(src: &str) -> Result<WeightedValue<(f64, f64)>,
 	}
 }
 
+/// Parses a float pair that can have negative values with a weight (e.g. "-5-5", "-10--5@2") into a WeightedValue<>
+pub fn parse_weighted_signed_float_pair(src: &str) -> Result<WeightedValue<(f64, f64)>, &'static str> {
+	match parse_weight(src) {
+		Ok((src_value, weight)) => match parse_signed_float_pair(src_value) {
+			Ok(value) => Ok(WeightedValue {
+				value,
+				weight,
+			}),
+			Err(err) => Err(err),
+		},
+		Err(err) => Err(err),
+	}
+}
+
+/// Parses a float pair triple with a weight (e.g. "1/0.5/0.5", "0.9-1/0.5/0.5@2") into a WeightedValue<>
+pub fn parse_weighted_float_pair_triple(src: &str) -> Result<WeightedValue<[(f64, f64); 3]>, &'static str> {
+	match parse_weight(src) {
+		Ok((src_value, weight)) => match parse_float_pair_triple(src_value) {
+			Ok(value) => Ok(WeightedValue {
+				value,
+				weight,
+			}),
+			Err(err) => Err(err),
+		},
+		Err(err) => Err(err),
+	}
+}
+
+/// Parses a tint with a weight (e.g. "#f80", "#4080ff/0.5@2") into a WeightedValue<>
+pub fn parse_weighted_tint(src: &str) -> Result<WeightedValue<([f32; 3], f32)>, &'static str> {
+	match parse_weight(src) {
+		Ok((src_value, weight)) => match parse_tint(src_value) {
+			Ok(value) => Ok(WeightedValue {
+				value,
+				weight,
+			}),
+			Err(err) => Err(err),
+		},
+		Err(err) => Err(err),
+	}
+}
+
 /// Parses a blending mode with a weight (e.g. "normal", "screen@2") into a WeightedValue<>
-pub fn parse_weighted_blending_mode(src: &str) -> Result<WeightedValue<BlendingMode>, &str> {
+pub fn parse_weighted_blending_mode(src: &str) -> Result<WeightedValue<BlendingMode>, &'static str> {
 	match parse_weight(src) {
 		Ok((src_value, weight)) => match BlendingMode::from_str(src_value) {
 			Ok(value) => Ok(WeightedValue {
@@ -123,3 +344,160 @@ pub fn parse_weighted_blending_mode(src: &str) -> Result<WeightedValue<BlendingM
 		Err(err) => Err(err),
 	}
 }
+
+/// Parses a blending mode or a group of blending modes with a weight (e.g. "normal", "darkening@2") into a list
+/// of WeightedValue<>; each mode in a group gets the same weight
+pub fn parse_weighted_blending_modes(src: &str) -> Result<Vec<WeightedValue<BlendingMode>>, &'static str> {
+	let (src_value, weight) = parse_weight(src)?;
+	match BlendingMode::from_group(src_value) {
+		Some(group) => Ok(group
+			.into_iter()
+			.map(|value| WeightedValue {
+				value,
+				weight,
+			})
+			.collect()),
+		None => Ok(vec![parse_weighted_blending_mode(src)?]),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn color_hex() {
+		assert_eq!(parse_color("#fff"), Ok([1.0, 1.0, 1.0]));
+		assert_eq!(parse_color("#F00"), Ok([1.0, 0.0, 0.0]));
+		assert_eq!(parse_color("#000000"), Ok([0.0, 0.0, 0.0]));
+		assert_eq!(parse_color("#ff8000"), Ok([1.0, 128.0 / 255.0, 0.0]));
+		assert_eq!(parse_color("#f80"), parse_color("#ff8800"));
+	}
+
+	#[test]
+	fn color_names() {
+		assert_eq!(parse_color("black"), Ok([0.0, 0.0, 0.0]));
+		assert_eq!(parse_color("White"), Ok([1.0, 1.0, 1.0]));
+		assert_eq!(parse_color("gray"), parse_color("grey"));
+	}
+
+	#[test]
+	fn color_invalid() {
+		for src in ["", "red", "fff", "#ff", "#ffff", "#fffffff", "#ggg", "#12345g", "#+1f", "#éa"] {
+			assert!(parse_color(src).is_err(), "{src:?} should not parse");
+		}
+	}
+
+	#[test]
+	fn background() {
+		assert_eq!(parse_background("transparent"), Ok([0.0, 0.0, 0.0, 0.0]));
+		assert_eq!(parse_background("#fff"), Ok([1.0, 1.0, 1.0, 1.0]));
+		assert!(parse_background("none").is_err());
+	}
+
+	#[test]
+	fn bit_depth() {
+		assert_eq!(parse_bit_depth("8"), Ok(8));
+		assert_eq!(parse_bit_depth("16"), Ok(16));
+		assert!(parse_bit_depth("32").is_err());
+		assert!(parse_bit_depth("0").is_err());
+	}
+
+	#[test]
+	fn quality() {
+		assert_eq!(parse_quality("1"), Ok(1));
+		assert_eq!(parse_quality("100"), Ok(100));
+		assert!(parse_quality("0").is_err());
+		assert!(parse_quality("101").is_err());
+		assert!(parse_quality("-1").is_err());
+	}
+
+	#[test]
+	fn downscale() {
+		assert_eq!(parse_downscale("1"), Ok(1.0));
+		assert_eq!(parse_downscale("0.5"), Ok(0.5));
+		assert!(parse_downscale("0").is_err());
+		assert!(parse_downscale("1.5").is_err());
+	}
+
+	#[test]
+	fn percentage() {
+		assert_eq!(parse_percentage("10"), Ok(10.0));
+		assert_eq!(parse_percentage("10%"), Ok(10.0));
+		assert_eq!(parse_percentage("0"), Ok(0.0));
+		assert_eq!(parse_percentage("100%"), Ok(100.0));
+		assert!(parse_percentage("101").is_err());
+		assert!(parse_percentage("-1%").is_err());
+		assert!(parse_percentage("%").is_err());
+	}
+
+	#[test]
+	fn threshold_and_amount() {
+		for parse in [parse_threshold, parse_amount] {
+			assert_eq!(parse("0"), Ok(0.0));
+			assert_eq!(parse("0.5"), Ok(0.5));
+			assert_eq!(parse("1"), Ok(1.0));
+			assert!(parse("1.01").is_err());
+			assert!(parse("-0.1").is_err());
+			assert!(parse("NaN").is_err());
+		}
+	}
+
+	#[test]
+	fn anchor() {
+		assert_eq!(parse_anchor("0"), Ok(0.0));
+		assert_eq!(parse_anchor("0.4"), Ok(0.4));
+		assert_eq!(parse_anchor("1"), Ok(1.0));
+		assert_eq!(parse_anchor("1.5"), Err("Anchor must be between 0 and 1"));
+		assert_eq!(parse_anchor("-0.5"), Err("Anchor must be between 0 and 1"));
+		assert!(parse_anchor("center").is_err());
+	}
+
+	#[test]
+	fn opacity_decay() {
+		assert_eq!(parse_opacity_decay("none"), Ok(OpacityDecay::None));
+		assert_eq!(parse_opacity_decay("Average"), Ok(OpacityDecay::Average));
+		assert_eq!(parse_opacity_decay("0.95"), Ok(OpacityDecay::Factor(0.95)));
+		assert!(parse_opacity_decay("2").is_err());
+		assert!(parse_opacity_decay("half").is_err());
+	}
+
+	#[test]
+	fn face_size() {
+		assert_eq!(parse_face_size("10"), Ok(SizeUnit::Pixels(10)));
+		assert_eq!(parse_face_size("50%"), Ok(SizeUnit::Fraction(0.5)));
+		assert_eq!(parse_face_size("1.5f"), Ok(SizeUnit::FaceRelative(1.5)));
+		assert!(parse_face_size("f").is_err());
+		assert!(parse_face_size("10px").is_err());
+	}
+
+	#[test]
+	fn vignette() {
+		assert_eq!(parse_vignette("40%"), Ok((SizeUnit::Fraction(0.4), 1.0)));
+		assert_eq!(parse_vignette("300/2"), Ok((SizeUnit::Pixels(300), 2.0)));
+		assert_eq!(parse_vignette("1.5f"), Ok((SizeUnit::FaceRelative(1.5), 1.0)));
+		assert!(parse_vignette("1/2/3").is_err());
+		assert!(parse_vignette("40%/strong").is_err());
+	}
+
+	#[test]
+	fn weighted_tint() {
+		assert_eq!(
+			parse_weighted_tint("#f80"),
+			Ok(WeightedValue {
+				value: ([1.0, 136.0 / 255.0, 0.0], 1.0),
+				weight: 1.0,
+			})
+		);
+		assert_eq!(
+			parse_weighted_tint("white/0.5@2"),
+			Ok(WeightedValue {
+				value: ([1.0, 1.0, 1.0], 0.5),
+				weight: 2.0,
+			})
+		);
+		assert!(parse_weighted_tint("#fff/2").is_err());
+		assert!(parse_weighted_tint("#fff/0.5/0.5").is_err());
+		assert!(parse_weighted_tint("#fff@1@2").is_err());
+	}
+}
@@ -0,0 +1,71 @@
+use std::io::{Write, stdout};
+use std::time::Instant;
+
+use crate::terminal;
+
+const BAR_WIDTH: usize = 20;
+
+/// A progress bar for the files being processed, with an estimate of the time remaining, like
+/// `[########------------] 42% 123/500 ETA 0:35`. It's only shown when the output is a terminal.
+pub struct ProgressBar {
+	total: usize,
+	start_time: Instant,
+	is_enabled: bool,
+}
+
+impl ProgressBar {
+	pub fn new(total: usize) -> ProgressBar {
+		ProgressBar {
+			total,
+			start_time: Instant::now(),
+			is_enabled: terminal::is_terminal(),
+		}
+	}
+
+	/// Draws the bar on the current line, leaving the cursor at its start, so anything printed next replaces it
+	pub fn draw(&self, current: usize) {
+		if !self.is_enabled {
+			return;
+		}
+		terminal::cursor_start_of_line();
+		terminal::erase_line_to_end();
+		print!("{}", self.render(current));
+		terminal::cursor_start_of_line();
+		stdout().flush().ok();
+	}
+
+	fn render(&self, current: usize) -> String {
+		let fraction = if self.total == 0 {
+			1.0
+		} else {
+			current.min(self.total) as f64 / self.total as f64
+		};
+		let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+		let eta = if current == 0 {
+			"?".to_string()
+		} else {
+			let elapsed = self.start_time.elapsed().as_secs_f64();
+			let remaining = elapsed / current as f64 * self.total.saturating_sub(current) as f64;
+			format_duration(remaining.round() as u64)
+		};
+		format!(
+			"[{}{}] {:.0}% {}/{} ETA {}",
+			"#".repeat(filled),
+			"-".repeat(BAR_WIDTH - filled),
+			fraction * 100.0,
+			current,
+			self.total,
+			eta
+		)
+	}
+}
+
+/// Formats a duration in seconds as "m:ss", or "h:mm:ss" when longer than an hour
+fn format_duration(seconds: u64) -> String {
+	let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+	if hours > 0 {
+		format!("{}:{:02}:{:02}", hours, minutes, seconds)
+	} else {
+		format!("{}:{:02}", minutes, seconds)
+	}
+}
@@ -1,8 +1,70 @@
 // Originally from https://github.com/zeh/random-art-generator/blob/main/src/generator/utils/random/mod.rs
 
+use std::path::Path;
+
 use crate::rng::Rng;
 use crate::units::{SizeUnit, WeightedValue};
 
+/// Creates a random number generator for a specific file, so the random values it produces don't depend on
+/// which other files are being used. The path is hashed with 64-bit FNV-1a, which is stable everywhere.
+pub fn get_rng_for_path(seed: u64, path: &Path) -> Rng {
+	let hash = path
+		.to_string_lossy()
+		.bytes()
+		.fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+	Rng::from_seed_u64(seed ^ hash)
+}
+
+/// Creates a random number generator for a specific frame of an animated file, like `get_rng_for_path()`, so
+/// each frame gets different random values. The first frame gets the same ones as the file.
+pub fn get_rng_for_frame(seed: u64, path: &Path, frame_index: usize) -> Rng {
+	get_rng_for_path(seed ^ (frame_index as u64).wrapping_mul(0x9e3779b97f4a7c15), path)
+}
+
+/// Shuffles entries in place, with the Fisher-Yates algorithm.
+pub fn shuffle<T>(rng: &mut Rng, entries: &mut [T]) {
+	for index in (1..entries.len()).rev() {
+		let other_index = rng.next_u32_range_inclusive(0, index as u32) as usize;
+		entries.swap(index, other_index);
+	}
+}
+
+/// Draws entries from a weighted list like cards from a deck, so they're used a balanced number of times
+/// instead of by chance. Each entry is in the deck as many times as its weight (rounded, and at least once),
+/// in order; with a random number generator, the deck is shuffled, and shuffled again every time it runs out.
+pub struct Deck {
+	cards: Vec<usize>,
+	next_card: usize,
+	rng: Option<Rng>,
+}
+
+impl Deck {
+	pub fn new<T>(entries: &[WeightedValue<T>], rng: Option<Rng>) -> Deck {
+		let cards = entries
+			.iter()
+			.enumerate()
+			.flat_map(|(index, entry)| std::iter::repeat_n(index, (entry.weight.round() as usize).max(1)))
+			.collect();
+		Deck {
+			cards,
+			next_card: 0,
+			rng,
+		}
+	}
+
+	/// Draws the next card, returning the index of its entry in the list.
+	pub fn draw(&mut self) -> usize {
+		if self.next_card == 0
+			&& let Some(rng) = &mut self.rng
+		{
+			shuffle(rng, &mut self.cards);
+		}
+		let card = self.cards[self.next_card];
+		self.next_card = (self.next_card + 1) % self.cards.len();
+		card
+	}
+}
+
 #[inline(always)]
 fn get_random_range(rng: &mut Rng, min: f64, pseudo_max: f64) -> f64 {
 	rng.next_f64_range(min, pseudo_max)
@@ -27,9 +89,23 @@ pub fn get_random_range_weighted(rng: &mut Rng, ranges: &Vec<WeightedValue<(f64,
 	get_random_range(rng, range.0, range.1)
 }
 
-fn get_random_size_range(rng: &mut Rng, min: &SizeUnit, max: &SizeUnit, pixel_size: u32) -> f64 {
-	let min_pixels = min.to_pixels(pixel_size);
-	let max_pixels = max.to_pixels(pixel_size);
+pub fn get_random_range_triple_weighted(
+	rng: &mut Rng,
+	ranges: &Vec<WeightedValue<[(f64, f64); 3]>>,
+) -> [f64; 3] {
+	let range = get_random_entry_weighted(rng, ranges);
+	range.map(|(min, pseudo_max)| get_random_range(rng, min, pseudo_max))
+}
+
+fn get_random_size_range(
+	rng: &mut Rng,
+	min: &SizeUnit,
+	max: &SizeUnit,
+	pixel_size: u32,
+	face_size: f32,
+) -> f64 {
+	let min_pixels = min.to_pixels_for_face(pixel_size, face_size);
+	let max_pixels = max.to_pixels_for_face(pixel_size, face_size);
 	get_random_range(rng, min_pixels as f64, max_pixels as f64)
 }
 
@@ -37,7 +113,66 @@ pub fn get_random_size_range_weighted(
 	rng: &mut Rng,
 	ranges: &Vec<WeightedValue<(SizeUnit, SizeUnit)>>,
 	pixel_size: u32,
+	face_size: f32,
 ) -> f64 {
 	let range = get_random_entry_weighted(rng, ranges);
-	get_random_size_range(rng, &range.0, &range.1, pixel_size)
+	get_random_size_range(rng, &range.0, &range.1, pixel_size, face_size)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn shuffle_is_permutation() {
+		for seed in 0..20 {
+			let mut rng = Rng::from_seed(seed);
+			let mut entries = (0..50).collect::<Vec<u32>>();
+			shuffle(&mut rng, &mut entries);
+			let mut sorted = entries.clone();
+			sorted.sort();
+			assert_eq!(sorted, (0..50).collect::<Vec<u32>>());
+		}
+		// Shuffling is deterministic for a seed
+		let shuffled = |seed| {
+			let mut entries = (0..50).collect::<Vec<u32>>();
+			shuffle(&mut Rng::from_seed(seed), &mut entries);
+			entries
+		};
+		assert_eq!(shuffled(7), shuffled(7));
+		assert_ne!(shuffled(7), shuffled(8));
+	}
+
+	#[test]
+	fn shuffle_is_uniform() {
+		// Each of the 6 orders of 3 entries should come up about as often
+		let mut rng = Rng::from_seed(1);
+		let mut counts = std::collections::HashMap::new();
+		for _ in 0..60000 {
+			let mut entries = [0, 1, 2];
+			shuffle(&mut rng, &mut entries);
+			*counts.entry(entries).or_insert(0) += 1;
+		}
+		assert_eq!(counts.len(), 6);
+		assert!(counts.values().all(|&count| (9000..=11000).contains(&count)), "{counts:?}");
+	}
+
+	#[test]
+	fn shuffle_empty_and_single() {
+		let mut rng = Rng::from_seed(1);
+		let mut empty: [u32; 0] = [];
+		shuffle(&mut rng, &mut empty);
+		let mut single = [1];
+		shuffle(&mut rng, &mut single);
+		assert_eq!(single, [1]);
+	}
+
+	#[test]
+	fn frames_get_their_own_generators() {
+		let path = Path::new("images/animation.gif");
+		let first_values = |mut rng: Rng| [rng.next(), rng.next(), rng.next()];
+		assert_eq!(first_values(get_rng_for_frame(1, path, 0)), first_values(get_rng_for_path(1, path)));
+		assert_ne!(first_values(get_rng_for_frame(1, path, 1)), first_values(get_rng_for_path(1, path)));
+		assert_ne!(first_values(get_rng_for_frame(1, path, 1)), first_values(get_rng_for_frame(1, path, 2)));
+	}
 }
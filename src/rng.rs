@@ -2,43 +2,59 @@
 
 use getrandom;
 
+// Multiplier and increment (stream) for PCG32, from the reference implementation
+const PCG_MULTIPLIER: u64 = 6364136223846793005;
+const PCG_INCREMENT: u64 = 1442695040888963407;
+
 pub struct Rng {
-	seed: u32,
-	value: u32,
+	seed: u64,
+	state: u64,
 }
 
 impl Rng {
 	/// Generate a new Prando pseudo-random number generator. Uses a pseudo-random seed.
 	pub fn new() -> Rng {
-		let seed = getrandom::u32().expect("Generating seed");
-		Rng::from_seed(seed)
+		let seed = getrandom::u64().expect("Generating seed");
+		Rng::from_seed_u64(seed)
 	}
 
 	/// Generate a new Prando pseudo-random number generator.
 	///
 	/// @param seed - A number that determines which pseudo-random number sequence will be created.
+	#[allow(dead_code)]
 	pub fn from_seed(seed: u32) -> Rng {
+		Rng::from_seed_u64(seed as u64)
+	}
+
+	/// Generate a new Prando pseudo-random number generator from a 64-bit seed.
+	///
+	/// @param seed - A number that determines which pseudo-random number sequence will be created.
+	pub fn from_seed_u64(seed: u64) -> Rng {
 		let mut rng = Rng {
 			seed,
-			value: 0,
+			state: 0,
 		};
 		rng.reset();
 		rng
 	}
 
 	#[inline(always)]
-	fn xorshift(mut value: u32) -> u32 {
-		// Xorshift*32
-		// Based on George Marsaglia's work: http://www.jstatsoft.org/v08/i14/paper
-		value ^= value.wrapping_shl(13);
-		value ^= value.wrapping_shr(17);
-		value ^= value.wrapping_shl(5);
-		value
+	fn recalculate(&mut self) {
+		// PCG32 (XSH RR), based on Melissa O'Neill's work: https://www.pcg-random.org/
+		self.state = self.state.wrapping_mul(PCG_MULTIPLIER).wrapping_add(PCG_INCREMENT);
 	}
 
 	#[inline(always)]
-	fn recalculate(&mut self) {
-		self.value = Rng::xorshift(self.value);
+	fn output(state: u64) -> u32 {
+		// Permutes the state into the output, so even close states (and seeds) produce unrelated numbers
+		let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+		let rotation = (state >> 59) as u32;
+		xorshifted.rotate_right(rotation)
+	}
+
+	/// The seed that determines the sequence of numbers generated.
+	pub fn seed(&self) -> u64 {
+		self.seed
 	}
 
 	/// Reset the pseudo-random number sequence back to its starting seed. Further calls to next()
@@ -53,7 +69,10 @@ impl Rng {
 	/// println!(rng.next()); // 0.6177754114889017 again
 	/// println!(rng.next()); // 0.5784605181725837 again
 	pub fn reset(&mut self) {
-		self.value = self.seed;
+		self.state = 0;
+		self.recalculate();
+		self.state = self.state.wrapping_add(self.seed);
+		self.recalculate();
 	}
 
 	/// Skips ahead in the sequence of numbers that are being generated. This is equivalent to
@@ -71,8 +90,9 @@ impl Rng {
 	///
 	/// @return The generated pseudo-random number.
 	pub fn next(&mut self) -> u32 {
+		let state = self.state;
 		self.recalculate();
-		self.value
+		Rng::output(state)
 	}
 
 	/// Generates a pseudo-random number between a lower (inclusive) and a higher (exclusive) bounds.
@@ -85,6 +105,28 @@ impl Rng {
 		self.next_f64_range(min as f64, pseudo_max as f64) as u32
 	}
 
+	/// Generates a pseudo-random number between a lower (inclusive) and a higher (inclusive) bounds. Unlike
+	/// next_u32_range(), all numbers in the range are equally likely.
+	///
+	/// @param min - The minimum number that can be randomly generated.
+	/// @param max - The maximum number that can be randomly generated (inclusive).
+	/// @return The generated pseudo-random number.
+	pub fn next_u32_range_inclusive(&mut self, min: u32, max: u32) -> u32 {
+		if min >= max {
+			return min;
+		}
+		let span = (max - min) as u64 + 1;
+		// Numbers past the last whole multiple of the span would make some results more likely, so they're
+		// rejected
+		let limit = (1u64 << 32) - (1u64 << 32) % span;
+		loop {
+			let value = self.next() as u64;
+			if value < limit {
+				return min + (value % span) as u32;
+			}
+		}
+	}
+
 	/// Generates a pseudo-random number between 0 (inclusive) and 1 (exclusive).
 	///
 	/// @return The generated pseudo-random number.
@@ -114,3 +156,89 @@ impl Rng {
 		self.next_f64() > 0.5f64
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Chi-square statistic of how many values fell in each bucket, against an even distribution
+	fn chi_square(counts: &[u32]) -> f64 {
+		let expected = counts.iter().sum::<u32>() as f64 / counts.len() as f64;
+		counts.iter().map(|&count| (count as f64 - expected).powi(2) / expected).sum()
+	}
+
+	// Chi-square value for 15 degrees of freedom (16 buckets) with a 0.1% chance of being exceeded by chance
+	const CHI_SQUARE_15_CRITICAL: f64 = 37.70;
+
+	#[test]
+	fn buckets_are_uniform() {
+		for seed in [0, 1, 12345678, u32::MAX] {
+			let mut rng = Rng::from_seed(seed);
+			let mut high_counts = [0u32; 16];
+			let mut low_counts = [0u32; 16];
+			for _ in 0..160000 {
+				let value = rng.next();
+				high_counts[(value >> 28) as usize] += 1;
+				low_counts[(value & 15) as usize] += 1;
+			}
+			assert!(chi_square(&high_counts) < CHI_SQUARE_15_CRITICAL, "seed {seed}: {high_counts:?}");
+			assert!(chi_square(&low_counts) < CHI_SQUARE_15_CRITICAL, "seed {seed}: {low_counts:?}");
+		}
+	}
+
+	#[test]
+	fn f64_buckets_are_uniform() {
+		let mut rng = Rng::from_seed(42);
+		let mut counts = [0u32; 16];
+		for _ in 0..160000 {
+			let value = rng.next_f64();
+			assert!((0.0..=1.0).contains(&value));
+			counts[((value * 16.0) as usize).min(15)] += 1;
+		}
+		assert!(chi_square(&counts) < CHI_SQUARE_15_CRITICAL, "{counts:?}");
+	}
+
+	#[test]
+	fn same_seed_same_sequence() {
+		let mut rng1 = Rng::from_seed(12345678);
+		let mut rng2 = Rng::from_seed_u64(12345678);
+		let sequence = (0..10).map(|_| rng1.next()).collect::<Vec<u32>>();
+		assert_eq!(sequence, (0..10).map(|_| rng2.next()).collect::<Vec<u32>>());
+		rng1.reset();
+		assert_eq!(sequence, (0..10).map(|_| rng1.next()).collect::<Vec<u32>>());
+		let mut other_rng = Rng::from_seed(12345679);
+		assert_ne!(sequence, (0..10).map(|_| other_rng.next()).collect::<Vec<u32>>());
+	}
+
+	#[test]
+	fn range_inclusive_reaches_both_ends() {
+		let mut rng = Rng::from_seed(1);
+		let mut counts = [0u32; 6];
+		for _ in 0..60000 {
+			let value = rng.next_u32_range_inclusive(10, 15);
+			assert!((10..=15).contains(&value));
+			counts[(value - 10) as usize] += 1;
+		}
+		assert!(counts.iter().all(|&count| (9000..=11000).contains(&count)), "{counts:?}");
+	}
+
+	#[test]
+	fn range_inclusive_single_value() {
+		let mut rng = Rng::from_seed(1);
+		assert_eq!(rng.next_u32_range_inclusive(0, 0), 0);
+		assert_eq!(rng.next_u32_range_inclusive(7, 7), 7);
+		assert_eq!(rng.next_u32_range_inclusive(u32::MAX, u32::MAX), u32::MAX);
+	}
+
+	#[test]
+	fn range_inclusive_full_range() {
+		let mut rng = Rng::from_seed(1);
+		let values = (0..1000).map(|_| rng.next_u32_range_inclusive(0, u32::MAX)).collect::<Vec<u32>>();
+		// Same as the raw output, since nothing needs to be rejected
+		let mut raw_rng = Rng::from_seed(1);
+		assert_eq!(values, (0..1000).map(|_| raw_rng.next()).collect::<Vec<u32>>());
+		assert!(values.iter().any(|&value| value > u32::MAX / 2));
+		let value = rng.next_u32_range_inclusive(1, u32::MAX);
+		assert!(value >= 1);
+	}
+}
@@ -0,0 +1,176 @@
+use image::{ImageBuffer, Rgba, Rgba32FImage};
+use strum_macros::{Display, EnumString};
+
+use crate::blending::{channel_u8_to_f32, srgb_to_linear};
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum StackMode {
+	#[strum(serialize = "mean")]
+	Mean,
+	#[strum(serialize = "median")]
+	Median,
+	#[strum(serialize = "max")]
+	Max,
+	#[strum(serialize = "min")]
+	Min,
+}
+
+/// Collects layers to be combined all at once at the end, rather than blended one on top of another.
+/// Each pixel is only combined from the layers that were actually painted on it.
+pub struct Stack {
+	mode: StackMode,
+	width: u32,
+	height: u32,
+	linear_light: bool,
+	// Sums for mean stacks, or the extreme values seen so far for max/min stacks
+	values: Vec<[f32; 3]>,
+	counts: Vec<u32>,
+	// For median stacks only: every sample painted on each pixel, which can take a lot of memory
+	// (3 bytes per pixel per layer)
+	samples: Vec<Vec<[u8; 3]>>,
+}
+
+impl Stack {
+	pub fn new(mode: StackMode, width: u32, height: u32, linear_light: bool) -> Stack {
+		let num_pixels = width as usize * height as usize;
+		let values = match mode {
+			StackMode::Median => Vec::new(),
+			_ => vec![[0.0, 0.0, 0.0]; num_pixels],
+		};
+		let samples = match mode {
+			StackMode::Median => vec![Vec::new(); num_pixels],
+			_ => Vec::new(),
+		};
+		Stack {
+			mode,
+			width,
+			height,
+			linear_light,
+			values,
+			counts: vec![0; num_pixels],
+			samples,
+		}
+	}
+
+	pub fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	/// Adds a layer's (sRGB-encoded) pixel to the stack
+	#[inline(always)]
+	pub fn add_pixel(&mut self, x: u32, y: u32, color: &[u8; 3]) {
+		let index = y as usize * self.width as usize + x as usize;
+		match self.mode {
+			StackMode::Mean => {
+				let color = color.map(|c| self.decode_channel(c));
+				let sum = &mut self.values[index];
+				sum[0] += color[0];
+				sum[1] += color[1];
+				sum[2] += color[2];
+			}
+			StackMode::Median => self.samples[index].push(*color),
+			StackMode::Max | StackMode::Min => {
+				let color = color.map(|c| self.decode_channel(c));
+				let is_first = self.counts[index] == 0;
+				let extreme = &mut self.values[index];
+				for (value, new_value) in extreme.iter_mut().zip(color) {
+					if is_first
+						|| (self.mode == StackMode::Max && new_value > *value)
+						|| (self.mode == StackMode::Min && new_value < *value)
+					{
+						*value = new_value;
+					}
+				}
+			}
+		}
+		self.counts[index] += 1;
+	}
+
+	/// Combines all layers into an image. Pixels that no layer was painted on are taken from the background
+	/// image; all others are opaque.
+	pub fn to_image(&self, background: &Rgba32FImage) -> Rgba32FImage {
+		let mut channel_samples: Vec<u8> = Vec::new();
+		ImageBuffer::from_fn(self.width, self.height, |x, y| {
+			let index = y as usize * self.width as usize + x as usize;
+			let count = self.counts[index];
+			if count == 0 {
+				return *background.get_pixel(x, y);
+			}
+			let color = match self.mode {
+				StackMode::Mean => self.values[index].map(|v| v / count as f32),
+				StackMode::Max | StackMode::Min => self.values[index],
+				StackMode::Median => [0, 1, 2].map(|channel| {
+					channel_samples.clear();
+					channel_samples.extend(self.samples[index].iter().map(|sample| sample[channel]));
+					channel_samples.sort_unstable();
+					let middle = channel_samples.len() / 2;
+					if channel_samples.len().is_multiple_of(2) {
+						(self.decode_channel(channel_samples[middle - 1])
+							+ self.decode_channel(channel_samples[middle]))
+							/ 2.0
+					} else {
+						self.decode_channel(channel_samples[middle])
+					}
+				}),
+			};
+			Rgba([color[0], color[1], color[2], 1.0])
+		})
+	}
+
+	/// Converts a layer's (sRGB-encoded) channel value to the space used by the stack
+	#[inline(always)]
+	fn decode_channel(&self, value: u8) -> f32 {
+		let value = channel_u8_to_f32(value);
+		if self.linear_light {
+			srgb_to_linear(value)
+		} else {
+			value
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn add_rect(stack: &mut Stack, (x, y, width, height): (u32, u32, u32, u32), color: [u8; 3]) {
+		for y in y..y + height {
+			for x in x..x + width {
+				stack.add_pixel(x, y, &color);
+			}
+		}
+	}
+
+	#[test]
+	fn mean_only_uses_layers_painted_on_each_pixel() {
+		let mut stack = Stack::new(StackMode::Mean, 7, 2, false);
+		add_rect(&mut stack, (0, 0, 4, 2), [255, 0, 51]);
+		add_rect(&mut stack, (2, 0, 4, 2), [0, 255, 153]);
+		let background = Rgba32FImage::from_pixel(7, 2, Rgba([0.1, 0.2, 0.3, 0.4]));
+		let image = stack.to_image(&background);
+		// Only the first layer, then both, then only the second one
+		assert_eq!(image.get_pixel(1, 1), &Rgba([1.0, 0.0, 0.2, 1.0]));
+		assert_eq!(image.get_pixel(3, 1), &Rgba([0.5, 0.5, 0.4, 1.0]));
+		assert_eq!(image.get_pixel(5, 1), &Rgba([0.0, 1.0, 0.6, 1.0]));
+		// Not painted at all
+		assert_eq!(image.get_pixel(6, 0), &Rgba([0.1, 0.2, 0.3, 0.4]));
+	}
+
+	#[test]
+	fn median_of_odd_and_even_numbers_of_samples() {
+		let mut stack = Stack::new(StackMode::Median, 3, 1, false);
+		// Each channel is sorted on its own
+		for color in [[10, 255, 51], [255, 0, 102], [51, 102, 0]] {
+			stack.add_pixel(0, 0, &color);
+		}
+		for color in [[0, 255, 51], [51, 0, 102], [102, 51, 0], [255, 102, 255]] {
+			stack.add_pixel(1, 0, &color);
+		}
+		stack.add_pixel(2, 0, &[51, 102, 153]);
+		let image = stack.to_image(&Rgba32FImage::new(3, 1));
+		assert_eq!(image.get_pixel(0, 0), &Rgba([0.2, 0.4, 0.2, 1.0]));
+		// With an even number, the two in the middle are averaged
+		assert_eq!(image.get_pixel(1, 0), &Rgba([0.3, 0.3, 0.3, 1.0]));
+		assert_eq!(image.get_pixel(2, 0), &Rgba([0.2, 0.4, 0.6, 1.0]));
+	}
+}
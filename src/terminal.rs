@@ -1,14 +1,29 @@
 // From https://github.com/zeh/random-art-generator/blob/main/src/generator/utils/terminal.rs
 // More info: https://en.wikipedia.org/wiki/ANSI_escape_code
 
+use std::io::{IsTerminal, stdout};
+use std::sync::OnceLock;
+
+/// Whether the standard output is a terminal; when it's not (e.g. piped to a file), escape codes are not used
+pub fn is_terminal() -> bool {
+	static IS_TERMINAL: OnceLock<bool> = OnceLock::new();
+	*IS_TERMINAL.get_or_init(|| stdout().is_terminal())
+}
+
 pub fn cursor_up() {
-	print!("\u{1b}[1;A");
+	if is_terminal() {
+		print!("\u{1b}[1;A");
+	}
 }
 
 pub fn cursor_start_of_line() {
-	print!("\u{1b}[1;G");
+	if is_terminal() {
+		print!("\u{1b}[1;G");
+	}
 }
 
 pub fn erase_line_to_end() {
-	print!("\u{1b}[0;K");
+	if is_terminal() {
+		print!("\u{1b}[0;K");
+	}
 }
@@ -0,0 +1,64 @@
+use std::time::{Duration, Instant};
+
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter};
+
+/// The parts of a run that take most of the time
+#[derive(Clone, Copy, Debug, Display, EnumIter, PartialEq)]
+pub enum Stage {
+	#[strum(serialize = "Globbing")]
+	Globbing,
+	#[strum(serialize = "Image decode")]
+	Decode,
+	#[strum(serialize = "Detection")]
+	Detection,
+	#[strum(serialize = "Resize")]
+	Resize,
+	#[strum(serialize = "Blend")]
+	Blend,
+}
+
+/// Total time spent in each stage. Files are processed in parallel, so their stages add up the time of all
+/// threads, and can take longer than the run itself.
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+	durations: [Duration; 5],
+}
+
+impl Timings {
+	/// Runs something, adding the time it takes to a stage.
+	pub fn time<T>(&mut self, stage: Stage, run: impl FnOnce() -> T) -> T {
+		let start = Instant::now();
+		let result = run();
+		self.add_duration(stage, start.elapsed());
+		result
+	}
+
+	/// Adds a time measured elsewhere to a stage.
+	pub fn add_duration(&mut self, stage: Stage, duration: Duration) {
+		self.durations[stage as usize] += duration;
+	}
+
+	/// Adds all the times of other timings, e.g. from a file processed in another thread.
+	pub fn add(&mut self, other: &Timings) {
+		for (duration, other_duration) in self.durations.iter_mut().zip(other.durations) {
+			*duration += other_duration;
+		}
+	}
+
+	/// Prints a table with the time of each stage, and the total time since a start.
+	pub fn print(&self, start: Instant) {
+		let total = start.elapsed();
+		println!("Timings:");
+		for stage in Stage::iter() {
+			let duration = self.durations[stage as usize];
+			println!(
+				"  {:<14}{:>10.3}s{:>8.1}%",
+				stage.to_string(),
+				duration.as_secs_f64(),
+				duration.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON) * 100.0
+			);
+		}
+		println!("  {:<14}{:>10.3}s", "Total (wall)", total.as_secs_f64());
+	}
+}
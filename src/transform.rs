@@ -0,0 +1,161 @@
+use image::{ImageBuffer, Pixel, Rgb, RgbImage, Rgba, RgbaImage, imageops};
+use strum_macros::{Display, EnumString};
+
+use crate::geom::{WHi, XYf, XYi, fit_inside, rotate_point, whf_to_whi};
+
+#[derive(Clone, Debug, Display, EnumString, PartialEq)]
+pub enum ImageFit {
+	#[strum(serialize = "cover")]
+	Cover,
+	#[strum(serialize = "contain")]
+	Contain,
+}
+
+/**
+ * Sample a pixel at any position with bilinear interpolation. Positions outside the image are transparent, so
+ * the coverage of the image fades out at its edges. Colors are weighted by their alpha, so the colors of
+ * transparent pixels (or the ones outside, where the edges are used) don't bleed into the others
+ */
+fn sample_bilinear(image: &RgbaImage, x: f32, y: f32) -> [u8; 4] {
+	let (width, height) = (image.width() as i64, image.height() as i64);
+	if width == 0 || height == 0 {
+		return [0, 0, 0, 0];
+	}
+	let (x, y) = (x - 0.5, y - 0.5);
+	let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+	let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+	let mut color = [0.0f32; 3];
+	let mut color_weight = 0.0;
+	let mut alpha = 0.0;
+	for (dx, dy, weight) in
+		[(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)), (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)]
+	{
+		let (sx, sy) = (x0 + dx, y0 + dy);
+		let pixel = image.get_pixel(sx.clamp(0, width - 1) as u32, sy.clamp(0, height - 1) as u32).0;
+		// Fully transparent pixels still count a little, so there's a color when all of them are transparent
+		let pixel_color_weight = weight * pixel[3].max(1) as f32;
+		for (value, channel) in color.iter_mut().zip(pixel) {
+			*value += channel as f32 * pixel_color_weight;
+		}
+		color_weight += pixel_color_weight;
+		if sx >= 0 && sx < width && sy >= 0 && sy < height {
+			alpha += weight * pixel[3] as f32;
+		}
+	}
+	[
+		(color[0] / color_weight).round() as u8,
+		(color[1] / color_weight).round() as u8,
+		(color[2] / color_weight).round() as u8,
+		alpha.round() as u8,
+	]
+}
+
+/**
+ * Flatten an image with transparency on top of a color (as 0-1 sRGB values), so its transparent areas show the
+ * color instead of whatever color values they had
+ */
+pub fn flatten_alpha(image: &RgbaImage, color: [f32; 3]) -> RgbImage {
+	RgbImage::from_fn(image.width(), image.height(), |x, y| {
+		let pixel = image.get_pixel(x, y).0;
+		let alpha = pixel[3] as f32 / 255.0;
+		Rgb([0, 1, 2].map(|c| (pixel[c] as f32 * alpha + color[c] * 255.0 * (1.0 - alpha)).round() as u8))
+	})
+}
+
+/**
+ * Rotate an image around a pivot point, by an angle in radians (clockwise). The resulting image is
+ * expanded to fit the whole rotated image, with its corners transparent; the new position of the pivot is
+ * returned with it
+ */
+pub fn rotate_image(image: &RgbaImage, angle: f32, pivot: XYf) -> (RgbaImage, XYf) {
+	let (width, height) = (image.width() as f32, image.height() as f32);
+	let corners =
+		[(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)].map(|xy| rotate_point(xy, pivot, angle));
+	let min_x = corners.iter().map(|xy| xy.0).fold(f32::INFINITY, f32::min);
+	let min_y = corners.iter().map(|xy| xy.1).fold(f32::INFINITY, f32::min);
+	let max_x = corners.iter().map(|xy| xy.0).fold(f32::NEG_INFINITY, f32::max);
+	let max_y = corners.iter().map(|xy| xy.1).fold(f32::NEG_INFINITY, f32::max);
+
+	let rotated = RgbaImage::from_fn((max_x - min_x).ceil() as u32, (max_y - min_y).ceil() as u32, |x, y| {
+		let src = rotate_point((x as f32 + 0.5 + min_x, y as f32 + 0.5 + min_y), pivot, -angle);
+		Rgba(sample_bilinear(image, src.0, src.1))
+	});
+	(rotated, (pivot.0 - min_x, pivot.1 - min_y))
+}
+
+/**
+ * Resize an image to fit an area, keeping its aspect ratio: either covering the whole area (and getting cropped),
+ * or being contained in it (and leaving empty space). Returns the resized image, and its offset so it's
+ * centered in the area
+ */
+pub fn fit_image<P: Pixel + 'static>(
+	image: &ImageBuffer<P, Vec<P::Subpixel>>,
+	size: WHi,
+	fit: &ImageFit,
+) -> (ImageBuffer<P, Vec<P::Subpixel>>, XYi) {
+	let area_size = (size.0 as f32, size.1 as f32);
+	let contained_size = fit_inside(area_size, (image.width() as f32, image.height() as f32));
+	let new_size = match fit {
+		ImageFit::Contain => contained_size,
+		ImageFit::Cover => {
+			let scale = (area_size.0 / contained_size.0).max(area_size.1 / contained_size.1);
+			(contained_size.0 * scale, contained_size.1 * scale)
+		}
+	};
+	let new_size = whf_to_whi(new_size);
+	let resized = imageops::resize(image, new_size.0.max(1), new_size.1.max(1), imageops::Lanczos3);
+	let offset =
+		((size.0 as i32 - resized.width() as i32) / 2, (size.1 as i32 - resized.height() as i32) / 2);
+	(resized, offset)
+}
+
+/**
+ * Resize an image to a different size, covering it and cropping whatever is left out (centered) if the aspect
+ * ratio is different
+ */
+pub fn resize_to_cover<P: Pixel + 'static>(
+	image: &ImageBuffer<P, Vec<P::Subpixel>>,
+	size: WHi,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+	let (resized, offset) = fit_image(image, size, &ImageFit::Cover);
+	imageops::crop_imm(&resized, (-offset.0).max(0) as u32, (-offset.1).max(0) as u32, size.0, size.1)
+		.to_image()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rotated_corners_are_transparent() {
+		let image = RgbaImage::from_pixel(40, 40, Rgba([200, 100, 50, 255]));
+		let (rotated, pivot) = rotate_image(&image, std::f32::consts::FRAC_PI_4, (20.0, 20.0));
+		let (width, height) = rotated.dimensions();
+		assert!(width > 40 && height > 40);
+		for (x, y) in [(0, 0), (width - 1, 0), (0, height - 1), (width - 1, height - 1)] {
+			assert_eq!(rotated.get_pixel(x, y).0[3], 0, "corner {x},{y}");
+		}
+		assert_eq!(rotated.get_pixel(pivot.0 as u32, pivot.1 as u32).0, [200, 100, 50, 255]);
+		// The edges fade out without changing their color
+		assert!(rotated.pixels().all(|pixel| pixel.0[3] == 0 || pixel.0[0..3] == [200, 100, 50]));
+	}
+
+	#[test]
+	fn sample_outside_is_transparent() {
+		let image = RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+		assert_eq!(sample_bilinear(&image, -10.0, 2.0)[3], 0);
+		assert_eq!(sample_bilinear(&image, 2.0, 100.0)[3], 0);
+		assert_eq!(sample_bilinear(&image, 2.0, 2.0), [255, 255, 255, 255]);
+		// Half a pixel past the edge, half the coverage
+		assert_eq!(sample_bilinear(&image, 0.0, 2.0)[3], 128);
+	}
+
+	#[test]
+	fn rotate_empty_image() {
+		assert_eq!(sample_bilinear(&RgbaImage::new(0, 0), 0.0, 0.0), [0, 0, 0, 0]);
+		assert_eq!(sample_bilinear(&RgbaImage::new(0, 5), 0.0, 0.0), [0, 0, 0, 0]);
+		let (rotated, _) = rotate_image(&RgbaImage::new(0, 0), 1.0, (0.0, 0.0));
+		assert_eq!(rotated.dimensions(), (0, 0));
+		rotate_image(&RgbaImage::new(0, 10), 1.0, (0.0, 5.0));
+	}
+}
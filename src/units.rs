@@ -3,6 +3,8 @@
 pub enum SizeUnit {
 	Fraction(f64),
 	Pixels(i64),
+	/// Multiple of the size of a face, as it's painted on the output image
+	FaceRelative(f64),
 }
 
 impl SizeUnit {
@@ -10,6 +12,14 @@ impl SizeUnit {
 		match self {
 			Self::Fraction(value) => (*value * total_size as f64).round() as i64,
 			Self::Pixels(value) => *value,
+			Self::FaceRelative(_) => panic!("Face-relative sizes can only be used for face layers"),
+		}
+	}
+
+	pub fn to_pixels_for_face(&self, total_size: u32, face_size: f32) -> i64 {
+		match self {
+			Self::FaceRelative(value) => (*value * face_size as f64).round() as i64,
+			_ => self.to_pixels(total_size),
 		}
 	}
 }